@@ -0,0 +1,304 @@
+use icon_theme::IconDirectory;
+
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::env;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::UNIX_EPOCH;
+
+static INDEXED_EXTS: &[&str] = &["png", "svg", "xpm"];
+static INDEX_HEADER: &str = "themed-icon-lookup index 1";
+
+///
+/// Files of all sub dirs in one base dir, icon name => (sub dir, extension).
+///
+/// `foo.symbolic.png` is indexed as `foo.symbolic` with extension `png`.
+///
+#[derive(Debug, Default)]
+struct BaseDirIndex {
+    icons: HashMap<String, Vec<(usize, &'static str)>>,
+}
+
+impl BaseDirIndex {
+    fn build(base_dir: &Path, sub_dirs: &[IconDirectory]) -> Self {
+        let mut r = Self::default();
+
+        for (i, sub) in sub_dirs.iter().enumerate() {
+            let entries = match fs::read_dir(base_dir.join(sub.name())) {
+                Ok(entries) => entries,
+                Err(_) => continue,
+            };
+
+            for entry in entries.filter_map(|x| x.ok()) {
+                // symlinks are followed like probing with `is_file`, dangling ones are skipped
+                let is_file = match entry.file_type() {
+                    Ok(x) if x.is_symlink() => fs::metadata(entry.path()).map(|x| x.is_file()).unwrap_or(false),
+                    Ok(x) => x.is_file(),
+                    Err(_) => false,
+                };
+                if !is_file { continue; }
+
+                let file_name = entry.file_name();
+                let (name, ext) = match file_name.to_str().and_then(split_ext) {
+                    Some(r) => r,
+                    None => continue,
+                };
+
+                if let Some(ext) = INDEXED_EXTS.iter().find(|x| **x == ext) {
+                    r.icons.entry(name.to_string()).or_default().push((i, ext));
+                }
+            }
+        }
+
+        r
+    }
+
+    ///
+    /// Load an index saved by `save`, `None` if the file is missing, broken or stale.
+    ///
+    /// The index is stale if the base dir, the list of sub dirs or the mtime of any
+    /// sub dir differs, adding or removing an icon changes the mtime of its dir.
+    ///
+    fn load(file: &Path, base_dir: &Path, sub_dirs: &[IconDirectory]) -> Option<Self> {
+        let mut lines = BufReader::new(fs::File::open(file).ok()?).lines();
+        let mut next_line = || lines.next().and_then(|x| x.ok());
+
+        if next_line()? != INDEX_HEADER { return None; }
+        if Path::new(&next_line()?) != base_dir { return None; }
+        if next_line()?.parse::<usize>().ok()? != sub_dirs.len() { return None; }
+
+        for sub in sub_dirs {
+            if next_line()? != format!("{}\t{}", dir_mtime(&base_dir.join(sub.name())), sub.name()) {
+                return None;
+            }
+        }
+
+        let mut r = Self::default();
+        while let Some(line) = next_line() {
+            let mut fields = line.split('\t');
+            let name = fields.next()?;
+            let mut files = vec![];
+
+            for field in fields {
+                let mut file = field.split(' ');
+                let sub: usize = file.next()?.parse().ok()?;
+                let ext = file.next()?;
+
+                if sub >= sub_dirs.len() { return None; }
+                files.push((sub, *INDEXED_EXTS.iter().find(|x| **x == ext)?));
+            }
+
+            r.icons.insert(name.to_string(), files);
+        }
+
+        Some(r)
+    }
+
+    /// Write the index to a temporary file then rename it, readers never see a partial index.
+    fn save(&self, file: &Path, base_dir: &Path, sub_dirs: &[IconDirectory]) -> io::Result<()> {
+        let base = base_dir.to_str().filter(|x| !x.contains('\n'))
+                           .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "unsupported base dir"))?;
+
+        if let Some(dir) = file.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let tmp = file.with_extension(format!("tmp{}", std::process::id()));
+        let mut w = io::BufWriter::new(fs::File::create(&tmp)?);
+
+        writeln!(w, "{}", INDEX_HEADER)?;
+        writeln!(w, "{}", base)?;
+        writeln!(w, "{}", sub_dirs.len())?;
+        for sub in sub_dirs {
+            writeln!(w, "{}\t{}", dir_mtime(&base_dir.join(sub.name())), sub.name())?;
+        }
+
+        for (name, files) in self.icons.iter() {
+            write!(w, "{}", name)?;
+            for &(sub, ext) in files {
+                write!(w, "\t{} {}", sub, ext)?;
+            }
+            writeln!(w)?;
+        }
+
+        w.into_inner()?.sync_all()?;
+        fs::rename(tmp, file)
+    }
+
+    fn contains(&self, sub_dir: usize, file_name: &str) -> bool {
+        let (name, ext) = match split_ext(file_name) {
+            Some(r) => r,
+            None => return false,
+        };
+
+        self.icons.get(name)
+            .map(|x| x.iter().any(|&(i, e)| i == sub_dir && e == ext))
+            .unwrap_or(false)
+    }
+}
+
+/// `secs.nanos` since the epoch, `-` if the dir doesn't exist.
+fn dir_mtime(dir: &Path) -> String {
+    fs::metadata(dir)
+        .and_then(|x| x.modified())
+        .ok()
+        .and_then(|x| x.duration_since(UNIX_EPOCH).ok())
+        .map(|x| format!("{}.{:09}", x.as_secs(), x.subsec_nanos()))
+        .unwrap_or_else(|| "-".to_string())
+}
+
+/// `$XDG_CACHE_HOME/themed-icon-lookup`, `$HOME/.cache/themed-icon-lookup` if not set.
+pub(crate) fn default_cache_dir() -> Option<PathBuf> {
+    let cache_home = match env::var("XDG_CACHE_HOME").ok().filter(|x| !x.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var("HOME").ok().filter(|x| !x.is_empty())?).join(".cache"),
+    };
+
+    Some(cache_home.join("themed-icon-lookup"))
+}
+
+fn split_ext(file_name: &str) -> Option<(&str, &str)> {
+    file_name.rfind('.').map(|i| (&file_name[..i], &file_name[i + 1..]))
+}
+
+///
+/// In-memory listing of icon files of a theme, each base dir is listed with
+/// `read_dir` on first use so lookups don't stat every candidate file.
+///
+/// With a `cache_dir` the listing of each base dir is also saved to disk and
+/// reused by later processes as long as the mtimes of its dirs don't change.
+///
+#[derive(Debug, Default)]
+pub(crate) struct DirIndex {
+    cache_dir: Option<PathBuf>,
+    base_dirs: RwLock<HashMap<PathBuf, Arc<BaseDirIndex>>>,
+}
+
+impl Clone for DirIndex {
+    fn clone(&self) -> Self {
+        Self {
+            cache_dir: self.cache_dir.clone(),
+            base_dirs: RwLock::new(self.base_dirs.read().unwrap().clone()),
+        }
+    }
+}
+
+impl DirIndex {
+    pub(crate) fn with_cache_dir(cache_dir: PathBuf) -> Self {
+        Self { cache_dir: Some(cache_dir), ..Default::default() }
+    }
+
+    /// Index file of `base_dir` in the cache dir, named by the hash of its path.
+    fn cache_file(&self, base_dir: &Path) -> Option<PathBuf> {
+        let mut hasher = DefaultHasher::new();
+        base_dir.hash(&mut hasher);
+
+        self.cache_dir.as_ref().map(|x| x.join(format!("{:016x}.index", hasher.finish())))
+    }
+
+    fn load_or_build(&self, base_dir: &Path, sub_dirs: &[IconDirectory]) -> BaseDirIndex {
+        let cache_file = match self.cache_file(base_dir) {
+            Some(f) => f,
+            None => return BaseDirIndex::build(base_dir, sub_dirs),
+        };
+
+        if let Some(index) = BaseDirIndex::load(&cache_file, base_dir, sub_dirs) {
+            return index;
+        }
+
+        let index = BaseDirIndex::build(base_dir, sub_dirs);
+        if let Err(e) = index.save(&cache_file, base_dir, sub_dirs) {
+            debug!("failed to save index of {} to {}: {}", base_dir.display(), cache_file.display(), e);
+        }
+
+        index
+    }
+
+    fn base_dir(&self, base_dir: &Path, sub_dirs: &[IconDirectory]) -> Arc<BaseDirIndex> {
+        if let Some(index) = self.base_dirs.read().unwrap().get(base_dir) {
+            return index.clone();
+        }
+
+        // list outside of the lock, a racing thread may list the same dir
+        let index = Arc::new(self.load_or_build(base_dir, sub_dirs));
+
+        self.base_dirs.write().unwrap()
+            .entry(base_dir.to_path_buf())
+            .or_insert(index)
+            .clone()
+    }
+
+    /// `base_dir/sub_dirs[sub_dir]/file_name` is an icon file.
+    pub(crate) fn contains(&self, base_dir: &Path, sub_dirs: &[IconDirectory], sub_dir: usize, file_name: &str) -> bool {
+        self.base_dir(base_dir, sub_dirs).contains(sub_dir, file_name)
+    }
+
+    /// Drop all listings, they are reloaded or rebuilt on next lookup.
+    pub(crate) fn invalidate(&self) {
+        self.base_dirs.write().unwrap().clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use dir_index::*;
+    use icon_theme::{scratch_theme, IconTheme};
+
+    use std::fs::OpenOptions;
+
+    #[test]
+    fn test_symlinks() {
+        use std::os::unix::fs::symlink;
+
+        let dir = Path::new("target/test-dir-index-symlinks");
+        scratch_theme::create(dir, &["first"]);
+        symlink("first.png", dir.join("apps/16/linked.png")).unwrap();
+        symlink("missing.png", dir.join("apps/16/dangling.png")).unwrap();
+        fs::create_dir(dir.join("apps/16/dir.png")).unwrap();
+
+        let theme = IconTheme::from_dir(dir).unwrap().with_dir_index(true);
+        assert_eq!(theme.lookup_icon(&"linked".into(), 16, 1), Some(dir.join("apps/16/linked.png")));
+        assert_eq!(theme.lookup_icon(&"dangling".into(), 16, 1), None);
+        assert_eq!(theme.lookup_icon(&"dir".into(), 16, 1), None);
+    }
+
+    #[test]
+    fn test_persistent_index() {
+        let dir = Path::new("target/test-persistent-index");
+        let theme_dir = dir.join("theme");
+        let cache_dir = dir.join("cache");
+        let _ = fs::remove_dir_all(dir);
+        scratch_theme::create(&theme_dir, &["first"]);
+
+        let theme = IconTheme::from_dir(&theme_dir).unwrap().with_persistent_index(&cache_dir);
+        assert_eq!(theme.lookup_icon(&"first".into(), 16, 1), Some(theme_dir.join("apps/16/first.png")));
+
+        let cache_file = fs::read_dir(&cache_dir).unwrap().next().unwrap().unwrap().path();
+        let content = fs::read_to_string(&cache_file).unwrap();
+        assert!(content.starts_with(INDEX_HEADER));
+        assert!(content.contains("first\t0 png\n"));
+
+        // a new theme object answers from the saved index
+        OpenOptions::new().append(true).open(&cache_file).unwrap().write_all(b"saved\t0 svg\n").unwrap();
+        let theme = IconTheme::from_dir(&theme_dir).unwrap().with_persistent_index(&cache_dir);
+        assert_eq!(theme.lookup_icon(&"saved".into(), 16, 1), Some(theme_dir.join("apps/16/saved.svg")));
+
+        // changed dirs make the saved index stale
+        scratch_theme::install(&theme_dir, "second");
+        scratch_theme::set_mtime(&theme_dir.join("apps/16"), 1);
+
+        let theme = IconTheme::from_dir(&theme_dir).unwrap().with_persistent_index(&cache_dir);
+        assert_eq!(theme.lookup_icon(&"saved".into(), 16, 1), None);
+        assert_eq!(theme.lookup_icon(&"second".into(), 16, 1), Some(theme_dir.join("apps/16/second.png")));
+
+        // broken index files are rebuilt
+        fs::write(&cache_file, "broken").unwrap();
+        let theme = IconTheme::from_dir(&theme_dir).unwrap().with_persistent_index(&cache_dir);
+        assert_eq!(theme.lookup_icon(&"first".into(), 16, 1), Some(theme_dir.join("apps/16/first.png")));
+        assert!(fs::read_to_string(&cache_file).unwrap().starts_with(INDEX_HEADER));
+    }
+}
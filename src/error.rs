@@ -0,0 +1,54 @@
+use ini;
+
+use std::error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+///
+/// Errors raised while loading an icon theme.
+///
+#[derive(Debug)]
+pub enum IconThemeError {
+    /// `index.theme` doesn't exist in the theme directory.
+    IndexNotFound(PathBuf),
+    /// `index.theme` exists but is not a valid INI file.
+    Parse(PathBuf, ini::ParseError),
+    /// Theme name can't be found in any base dir.
+    ThemeNotFound(String),
+    /// Any other io error, e.g. permission denied.
+    Io(PathBuf, io::Error),
+}
+
+impl IconThemeError {
+    pub(crate) fn from_ini<T: Into<PathBuf>>(path: T, err: ini::Error) -> Self {
+        let path = path.into();
+
+        match err {
+            ini::Error::Io(ref e) if e.kind() == io::ErrorKind::NotFound => IconThemeError::IndexNotFound(path),
+            ini::Error::Io(e) => IconThemeError::Io(path, e),
+            ini::Error::Parse(e) => IconThemeError::Parse(path, e),
+        }
+    }
+}
+
+impl fmt::Display for IconThemeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            IconThemeError::IndexNotFound(ref path) => write!(f, "{} not found", path.display()),
+            IconThemeError::Parse(ref path, ref e) => write!(f, "failed to parse {}: {}", path.display(), e),
+            IconThemeError::ThemeNotFound(ref name) => write!(f, "icon theme {} not found", name),
+            IconThemeError::Io(ref path, ref e) => write!(f, "failed to read {}: {}", path.display(), e),
+        }
+    }
+}
+
+impl error::Error for IconThemeError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            IconThemeError::Parse(_, ref e) => Some(e),
+            IconThemeError::Io(_, ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
@@ -1,9 +1,12 @@
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
 
 use icon_lookup;
+use icon_name::{IconName, TextDirection};
+use lookup_options::IconLookupOptions;
 
 use std::mem;
 use std::ptr;
-use std::os::raw::c_char;
+use std::os::raw::{c_char, c_int};
 use std::ffi::{CStr, CString};
 
 macro_rules! c_strify {
@@ -24,7 +27,7 @@ macro_rules! c_strify {
 
 macro_rules! c_str {
     ($c_str: ident) => {
-        unsafe { CStr::from_ptr($c_str).to_string_lossy() };
+        unsafe { CStr::from_ptr($c_str).to_string_lossy() }
     };
 }
 
@@ -53,10 +56,100 @@ pub extern "C" fn find_icon(icon: *const c_char, size: i32, scale: i32) -> *cons
     c_strify!(icon_lookup::find_icon(icon, size, scale))
 }
 
+/// `direction` is 1 for left-to-right, 2 for right-to-left, other values look up undirected names.
+#[no_mangle]
+pub extern "C" fn find_icon_with_direction(icon: *const c_char, size: i32, scale: i32, direction: c_int) -> *const c_char {
+
+    let direction = match direction {
+        1 => TextDirection::Ltr,
+        2 => TextDirection::Rtl,
+        _ => TextDirection::None,
+    };
+    let icon = IconName::from(c_str!(icon)).with_direction(direction);
+
+    c_strify!(icon_lookup::find_icon(icon, size, scale))
+}
+
+/// `residual_scale` receives the factor to scale the found image by, it may be null.
+#[no_mangle]
+pub extern "C" fn find_icon_with_fractional_scale(icon: *const c_char, size: i32, scale: f64, residual_scale: *mut f64) -> *const c_char {
+
+    let icon = c_str!(icon);
+    let options = IconLookupOptions::default();
+    let info = icon_lookup::find_icon_info_fractional(icon, size, scale, &options);
+
+    if let Some(ref info) = info {
+        if !residual_scale.is_null() {
+            unsafe { *residual_scale = info.residual_scale(); }
+        }
+    }
+
+    c_strify!(info.map(|x| x.into_path()))
+}
+
+#[no_mangle]
+pub extern "C" fn set_theme_cache_capacity(capacity: usize) {
+    icon_lookup::set_theme_cache_capacity(capacity);
+}
+
+#[no_mangle]
+pub extern "C" fn theme_cache_capacity() -> usize {
+    icon_lookup::theme_cache_capacity()
+}
+
+/// Drop a cached theme, returns whether it was cached.
+#[no_mangle]
+pub extern "C" fn purge_theme(theme: *const c_char) -> bool {
+
+    let theme = c_str!(theme);
+
+    icon_lookup::purge_theme(theme)
+}
+
+#[no_mangle]
+pub extern "C" fn purge_themes() {
+    icon_lookup::purge_themes();
+}
+
+/// Null-terminated array of cached theme names, free it with `free_cstring_array`.
+#[no_mangle]
+pub extern "C" fn cached_themes() -> *mut *mut c_char {
+    cstring_array(icon_lookup::cached_themes())
+}
+
+/// Null-terminated array freed by `free_cstring_array`.
+fn cstring_array(strings: Vec<String>) -> *mut *mut c_char {
+
+    let mut array: Vec<*mut c_char> = strings.into_iter()
+                                             .filter_map(|x| CString::new(x).ok())
+                                             .map(|x| x.into_raw())
+                                             .collect();
+    array.push(ptr::null_mut());
+
+    Box::into_raw(array.into_boxed_slice()) as *mut *mut c_char
+}
+
+#[no_mangle]
+pub extern "C" fn free_cstring_array(array: *mut *mut c_char) {
+    if array.is_null() {
+        return;
+    }
+
+    unsafe {
+        let mut len = 0;
+        while !(*array.add(len)).is_null() {
+            drop(CString::from_raw(*array.add(len)));
+            len += 1;
+        }
+
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(array, len + 1)));
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn free_cstring(cstring: *mut c_char) {
     if !cstring.is_null() {
-        unsafe { CString::from_raw(cstring); }
+        unsafe { drop(CString::from_raw(cstring)); }
     }
 }
 
@@ -73,5 +166,47 @@ mod test {
 
         // should't be crashed
         free_cstring(nullptr);
+        free_cstring_array(ptr::null_mut());
+    }
+
+    #[test]
+    fn test_unknown_direction() {
+        let icon = CString::new("TestAppIcon").unwrap();
+
+        for direction in &[0, 1, 2, 3, -1] {
+            let path = find_icon_with_direction(icon.as_ptr(), 16, 1, *direction);
+            assert!(!path.is_null());
+            free_cstring(path as *mut c_char);
+        }
+    }
+
+    #[test]
+    fn test_cached_themes() {
+        // the default lookup is shared by other tests
+        let lookup = icon_lookup::IconLookup::new(vec!["tests/icons"]);
+        let _ = lookup.theme("themed").unwrap();
+        let _ = lookup.theme("hicolor").unwrap();
+
+        let themes = cstring_array(lookup.cached_themes());
+        let mut names = vec![];
+        unsafe {
+            let mut p = themes;
+            while !(*p).is_null() {
+                names.push(CStr::from_ptr(*p).to_string_lossy().into_owned());
+                p = p.add(1);
+            }
+        }
+        free_cstring_array(themes);
+
+        assert_eq!(names, vec!["hicolor", "themed"]);
+
+        let empty = cstring_array(vec![]);
+        assert!(unsafe { (*empty).is_null() });
+        free_cstring_array(empty);
+
+        let themes = cached_themes();
+        assert!(!themes.is_null());
+        free_cstring_array(themes);
+        assert!(theme_cache_capacity() > 0);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,629 @@
+use icon_theme::{IconDirectory, IconTheme};
+
+use ini::Ini;
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Image flags of `icon-theme.cache`.
+pub(crate) const FLAG_XPM: u16 = 1;
+pub(crate) const FLAG_SVG: u16 = 2;
+pub(crate) const FLAG_PNG: u16 = 4;
+pub(crate) const FLAG_HAS_ICON_FILE: u16 = 8;
+
+const NONE: u32 = 0xffff_ffff;
+
+/// `g_spaced_primes_closest` of GLib, the hash table has `n_icons / 3` buckets.
+static SPACED_PRIMES: &[u32] = &[
+    11, 19, 37, 73, 109, 163, 251, 367, 557, 823, 1237, 1861, 2777, 4177, 6247, 9371, 14057,
+    21089, 31627, 47431, 71143, 106721, 160073, 240101, 360163, 540217, 810343, 1215497,
+    1823231, 2734867, 4102283, 6153409, 9230113, 13845163,
+];
+
+fn spaced_primes_closest(num: u32) -> u32 {
+    SPACED_PRIMES.iter().cloned().find(|x| *x > num).unwrap_or(SPACED_PRIMES[SPACED_PRIMES.len() - 1])
+}
+
+/// Hash of icon names, GTK hashes `signed char`s.
+pub(crate) fn icon_name_hash(name: &str) -> u32 {
+    name.bytes().fold(0u32, |h, c| (h << 5).wrapping_sub(h).wrapping_add(c as i8 as u32))
+}
+
+/// Image flag of a file extension.
+fn ext_flag(ext: &str) -> Option<u16> {
+    match ext {
+        "png" => Some(FLAG_PNG),
+        "svg" => Some(FLAG_SVG),
+        "xpm" => Some(FLAG_XPM),
+        _ => None,
+    }
+}
+
+///
+/// Reader of `icon-theme.cache` which, unlike the `gtk-icon-cache` crate,
+/// exposes the image flags, so file names are known without probing.
+///
+/// Directories of the cache are mapped to the theme directories it's opened with.
+///
+pub(crate) struct GtkIconCacheReader {
+    data: Vec<u8>,
+    hash_offset: usize,
+    n_buckets: u32,
+    /// cache directory index => theme directory index
+    dir_map: Vec<Option<usize>>,
+}
+
+impl fmt::Debug for GtkIconCacheReader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("GtkIconCacheReader")
+         .field("len", &self.data.len())
+         .field("n_buckets", &self.n_buckets)
+         .field("dir_map", &self.dir_map)
+         .finish()
+    }
+}
+
+impl GtkIconCacheReader {
+    pub(crate) fn open(path: &Path, sub_dirs: &[IconDirectory]) -> io::Result<Self> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid icon cache");
+
+        let mut r = Self {
+            data: fs::read(path)?,
+            hash_offset: 0,
+            n_buckets: 0,
+            dir_map: vec![],
+        };
+
+        if r.card16(0) != Some(1) || r.card16(2) != Some(0) { return Err(invalid()); }
+
+        r.hash_offset = r.card32(4).ok_or_else(invalid)? as usize;
+        r.n_buckets = r.card32(r.hash_offset).filter(|x| *x > 0).ok_or_else(invalid)?;
+
+        let dir_list = r.card32(8).ok_or_else(invalid)? as usize;
+        let n_dirs = r.card32(dir_list).ok_or_else(invalid)? as usize;
+        let positions: HashMap<&str, usize> = sub_dirs.iter().enumerate().map(|(i, x)| (x.name(), i)).collect();
+
+        let mut dir_map = Vec::with_capacity(n_dirs.min(u16::MAX as usize));
+        for i in 0..n_dirs {
+            let name = r.card32(dir_list + 4 + 4 * i).and_then(|x| r.string(x as usize)).ok_or_else(invalid)?;
+            dir_map.push(positions.get(name).cloned());
+        }
+        r.dir_map = dir_map;
+
+        Ok(r)
+    }
+
+    fn card16(&self, offset: usize) -> Option<u16> {
+        self.data.get(offset..offset + 2).map(|x| u16::from_be_bytes([x[0], x[1]]))
+    }
+
+    fn card32(&self, offset: usize) -> Option<u32> {
+        self.data.get(offset..offset + 4).map(|x| u32::from_be_bytes([x[0], x[1], x[2], x[3]]))
+    }
+
+    fn string(&self, offset: usize) -> Option<&str> {
+        let data = self.data.get(offset..)?;
+        let end = data.iter().position(|x| *x == 0)?;
+
+        ::std::str::from_utf8(&data[..end]).ok()
+    }
+
+    /// (theme directory index, flags) of each image of the icon.
+    pub(crate) fn images(&self, name: &str) -> Vec<(usize, u16)> {
+        let bucket = (icon_name_hash(name) % self.n_buckets) as usize;
+        let mut offset = self.card32(self.hash_offset + 4 + 4 * bucket);
+
+        // a broken chain can't loop longer than the file
+        let mut hops = 0;
+        while let Some(icon) = offset.filter(|x| *x != NONE).map(|x| x as usize) {
+            hops += 1;
+            if hops > self.data.len() / 12 { break; }
+
+            if self.card32(icon + 4).and_then(|x| self.string(x as usize)) == Some(name) {
+                let list = match self.card32(icon + 8) {
+                    Some(list) => list as usize,
+                    None => break,
+                };
+                let n_images = self.card32(list).unwrap_or(0) as usize;
+
+                // a broken count can't list more images than the file holds
+                if n_images > self.data.len().saturating_sub(list + 4) / 8 { break; }
+
+                return (0..n_images).filter_map(|i| {
+                    let dir = self.card16(list + 4 + 8 * i)? as usize;
+                    let flags = self.card16(list + 6 + 8 * i)?;
+
+                    self.dir_map.get(dir).cloned().flatten().map(|x| (x, flags))
+                }).collect();
+            }
+
+            offset = self.card32(icon);
+        }
+
+        vec![]
+    }
+
+    ///
+    /// Theme directories containing `file_name`, e.g. `foo.png`.
+    ///
+    /// Like GTK, `foo-symbolic.symbolic.png` is cached as `foo-symbolic.symbolic`
+    /// with `FLAG_PNG`.
+    ///
+    pub(crate) fn dirs_of_file(&self, file_name: &str) -> Vec<usize> {
+        let (stem, flag) = match file_name.rfind('.').map(|x| (&file_name[..x], ext_flag(&file_name[x + 1..]))) {
+            Some((stem, Some(flag))) => (stem, flag),
+            _ => return vec![],
+        };
+
+        self.images(stem).into_iter()
+            .filter(|x| x.1 & flag != 0)
+            .map(|x| x.0)
+            .collect()
+    }
+}
+
+/// `[Icon Data]` of a `.icon` file.
+#[derive(Debug, Default)]
+struct IconData {
+    embedded_rect: Option<[u16; 4]>,
+    attach_points: Vec<(u16, u16)>,
+    /// (lang, name), `C` for the untranslated name
+    display_names: Vec<(String, String)>,
+}
+
+impl IconData {
+    fn from_file(path: &Path) -> Option<Self> {
+        let f = Ini::load_from_file_noescape(path).ok()?;
+        let properties = f.section(Some("Icon Data"))?;
+        let mut r = Self::default();
+
+        if let Some(rect) = properties.get("EmbeddedTextRectangle") {
+            let rect: Vec<u16> = rect.split(',').filter_map(|x| x.trim().parse().ok()).collect();
+            if rect.len() == 4 {
+                r.embedded_rect = Some([rect[0], rect[1], rect[2], rect[3]]);
+            }
+        }
+
+        if let Some(points) = properties.get("AttachPoints") {
+            r.attach_points = points.split('|')
+                                    .filter_map(|x| {
+                                        let mut p = x.split(',').map(|x| x.trim().parse().ok());
+                                        Some((p.next()??, p.next()??))
+                                    })
+                                    .collect();
+        }
+
+        for (key, value) in properties.iter() {
+            if !key.starts_with("DisplayName") { continue; }
+
+            let lang = match (key.find('['), key.find(']')) {
+                (Some(open), Some(close)) if open < close => &key[open + 1..close],
+                _ => "C",
+            };
+
+            r.display_names.push((lang.to_string(), value.to_string()));
+        }
+
+        Some(r)
+    }
+}
+
+/// An icon in one directory.
+#[derive(Debug)]
+struct Image {
+    dir: u16,
+    flags: u16,
+    icon_data: Option<IconData>,
+}
+
+///
+/// Write `icon-theme.cache` files in the format of `gtk-update-icon-cache`.
+///
+/// Like GTK, all directories of the theme dir are scanned in sorted order, and
+/// a directory is listed when its first icon is found. The output is identical
+/// to `gtk-update-icon-cache` without `--include-image-data`, except for the
+/// order of icons sharing a hash bucket.
+///
+/// Embedding pixel data is not supported: the image data section only holds
+/// the meta data of `.icon` files, its pixel data offset is always 0, so GTK
+/// loads images from their files.
+///
+/// ```no_run
+/// use themed_icon_lookup::{GtkIconCacheWriter, IconTheme};
+///
+/// let theme = IconTheme::from_dir("/usr/share/icons/hicolor").unwrap();
+///
+/// GtkIconCacheWriter::new(&theme).update().unwrap();
+/// ```
+///
+#[derive(Debug, Clone)]
+pub struct GtkIconCacheWriter {
+    theme_dir: PathBuf,
+}
+
+/// Icons found by `GtkIconCacheWriter::scan`.
+#[derive(Debug, Default)]
+struct ScanResult {
+    directories: Vec<String>,
+    icons: BTreeMap<String, Vec<Image>>,
+}
+
+impl GtkIconCacheWriter {
+    /// Cache the theme dir `index.theme` of `theme` is loaded from.
+    pub fn new(theme: &IconTheme) -> Self {
+        Self::with_theme_dir(theme.base_dirs().first().cloned().unwrap_or_default())
+    }
+
+    pub fn with_theme_dir<T: Into<PathBuf>>(theme_dir: T) -> Self {
+        Self { theme_dir: theme_dir.into() }
+    }
+
+    pub fn theme_dir(&self) -> &Path {
+        &self.theme_dir
+    }
+
+    fn scan(&self) -> io::Result<ScanResult> {
+        let mut r = ScanResult::default();
+
+        self.scan_dir(None, &mut r)?;
+
+        // `.icon` files without an image are dropped, and images are listed
+        // from the last directory, like gtk-update-icon-cache
+        for images in r.icons.values_mut() {
+            images.retain(|x| x.flags != FLAG_HAS_ICON_FILE);
+            images.sort_by_key(|x| ::std::cmp::Reverse(x.dir));
+        }
+        r.icons.retain(|_, images| !images.is_empty());
+
+        Ok(r)
+    }
+
+    fn scan_dir(&self, sub_dir: Option<&str>, r: &mut ScanResult) -> io::Result<()> {
+        let dir = match sub_dir {
+            Some(sub_dir) => self.theme_dir.join(sub_dir),
+            None => self.theme_dir.clone(),
+        };
+
+        let mut names: Vec<String> = fs::read_dir(&dir)?
+                                         .filter_map(|x| x.ok())
+                                         .filter_map(|x| x.file_name().into_string().ok())
+                                         .collect();
+        names.sort();
+
+        for file_name in names.iter() {
+            let path = dir.join(file_name);
+
+            // follow symlinks like GTK does
+            if path.is_dir() {
+                let sub_sub_dir = match sub_dir {
+                    Some(sub_dir) => format!("{}/{}", sub_dir, file_name),
+                    None => file_name.clone(),
+                };
+
+                // unreadable dirs are skipped
+                let _ = self.scan_dir(Some(&sub_sub_dir), r);
+                continue;
+            }
+
+            // icons in the theme dir itself are ignored
+            let sub_dir = match sub_dir {
+                Some(sub_dir) => sub_dir,
+                None => continue,
+            };
+
+            let (name, flag) = match file_name.rfind('.').map(|x| file_name.split_at(x)) {
+                Some((stem, ".png")) => (stem, FLAG_PNG),
+                Some((stem, ".svg")) => (stem, FLAG_SVG),
+                Some((stem, ".xpm")) => (stem, FLAG_XPM),
+                Some((stem, ".icon")) => (stem, FLAG_HAS_ICON_FILE),
+                _ => continue,
+            };
+
+            let dir_index = match r.directories.iter().position(|x| x == sub_dir) {
+                Some(i) => i,
+                None => {
+                    r.directories.push(sub_dir.to_string());
+                    r.directories.len() - 1
+                },
+            };
+
+            if dir_index > u16::MAX as usize {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "too many directories"));
+            }
+
+            let images = r.icons.entry(name.to_string()).or_default();
+            let image = match images.iter().position(|x| x.dir == dir_index as u16) {
+                Some(pos) => &mut images[pos],
+                None => {
+                    images.push(Image { dir: dir_index as u16, flags: 0, icon_data: None });
+                    images.last_mut().unwrap()
+                },
+            };
+
+            image.flags |= flag;
+            if flag == FLAG_HAS_ICON_FILE {
+                image.icon_data = IconData::from_file(&path);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Content of the cache file.
+    pub fn to_bytes(&self) -> io::Result<Vec<u8>> {
+        let ScanResult { directories, icons } = self.scan()?;
+        let mut w = CacheBuffer::default();
+
+        // header
+        w.put_u16(1);
+        w.put_u16(0);
+        w.put_u32(12);
+        let dir_list_offset = w.put_placeholder();
+
+        // hash table
+        let n_buckets = spaced_primes_closest(icons.len() as u32 / 3);
+        let mut buckets: Vec<Vec<(&String, &Vec<Image>)>> = (0..n_buckets).map(|_| vec![]).collect();
+        for icon in icons.iter() {
+            buckets[(icon_name_hash(icon.0) % n_buckets) as usize].push(icon);
+        }
+
+        w.put_u32(n_buckets);
+        let bucket_table = w.len();
+        for _ in 0..n_buckets {
+            w.put_u32(NONE);
+        }
+
+        for (i, bucket) in buckets.iter().enumerate() {
+            let mut chain_slot = bucket_table + 4 * i;
+
+            for &(name, images) in bucket.iter() {
+                let offset = w.len();
+                w.set_u32(chain_slot, offset as u32);
+
+                chain_slot = w.len();
+                w.put_u32(NONE);
+                let name_offset = w.put_placeholder();
+                let image_list_offset = w.put_placeholder();
+
+                w.set_here(name_offset);
+                w.put_string(name);
+
+                w.set_here(image_list_offset);
+                w.put_u32(images.len() as u32);
+                let mut data_slots = vec![];
+                for image in images.iter() {
+                    w.put_u16(image.dir);
+                    w.put_u16(image.flags);
+                    data_slots.push(w.put_u32_value(0));
+                }
+
+                for (image, slot) in images.iter().zip(data_slots) {
+                    if let Some(ref data) = image.icon_data {
+                        w.set_here(slot);
+                        w.put_image_data(data);
+                    }
+                }
+            }
+        }
+
+        // directory list
+        w.set_here(dir_list_offset);
+        w.put_u32(directories.len() as u32);
+        let dir_slots: Vec<usize> = directories.iter().map(|_| w.put_placeholder()).collect();
+        for (dir, slot) in directories.iter().zip(dir_slots) {
+            w.set_here(slot);
+            w.put_string(dir);
+        }
+
+        Ok(w.buf)
+    }
+
+    /// Write the cache to `path` through a temporary file, readers never see a partial cache.
+    pub fn write_to<T: AsRef<Path>>(&self, path: T) -> io::Result<()> {
+        let path = path.as_ref();
+        let bytes = self.to_bytes()?;
+        let tmp = path.with_extension(format!("tmp{}", ::std::process::id()));
+
+        let mut f = fs::File::create(&tmp)?;
+        f.write_all(&bytes)?;
+        f.sync_all()?;
+
+        fs::rename(tmp, path)
+    }
+
+    /// Write `icon-theme.cache` in the theme dir, return its path.
+    pub fn update(&self) -> io::Result<PathBuf> {
+        let path = self.theme_dir.join("icon-theme.cache");
+        self.write_to(&path)?;
+
+        Ok(path)
+    }
+}
+
+/// Big-endian writer with offsets to patch later.
+#[derive(Default)]
+struct CacheBuffer {
+    buf: Vec<u8>,
+}
+
+impl CacheBuffer {
+    fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    fn put_u16(&mut self, v: u16) {
+        self.buf.extend_from_slice(&v.to_be_bytes());
+    }
+
+    fn put_u32(&mut self, v: u32) {
+        self.buf.extend_from_slice(&v.to_be_bytes());
+    }
+
+    /// Write `v` and return its offset.
+    fn put_u32_value(&mut self, v: u32) -> usize {
+        let offset = self.len();
+        self.put_u32(v);
+        offset
+    }
+
+    /// Write an offset set by `set_here` later.
+    fn put_placeholder(&mut self) -> usize {
+        self.put_u32_value(0)
+    }
+
+    fn set_u32(&mut self, at: usize, v: u32) {
+        self.buf[at..at + 4].copy_from_slice(&v.to_be_bytes());
+    }
+
+    /// Point the offset at `slot` to the current end.
+    fn set_here(&mut self, slot: usize) {
+        let offset = self.len() as u32;
+        self.set_u32(slot, offset);
+    }
+
+    /// NUL terminated and padded to 4 bytes.
+    fn put_string(&mut self, s: &str) {
+        self.buf.extend_from_slice(s.as_bytes());
+        let padding = 4 - s.len() % 4;
+        self.buf.extend((0..padding).map(|_| 0));
+    }
+
+    fn put_image_data(&mut self, data: &IconData) {
+        // pixel data is not supported, see `GtkIconCacheWriter`
+        self.put_u32(0);
+        let meta_data = self.len() as u32 + 4;
+        self.put_u32(meta_data);
+
+        let rect_offset = self.put_placeholder();
+        let attach_points_offset = self.put_placeholder();
+        let display_names_offset = self.put_placeholder();
+
+        if let Some(rect) = data.embedded_rect {
+            self.set_here(rect_offset);
+            for v in rect.iter() {
+                self.put_u16(*v);
+            }
+        }
+
+        if !data.attach_points.is_empty() {
+            self.set_here(attach_points_offset);
+            self.put_u32(data.attach_points.len() as u32);
+            for &(x, y) in data.attach_points.iter() {
+                self.put_u16(x);
+                self.put_u16(y);
+            }
+        }
+
+        if !data.display_names.is_empty() {
+            self.set_here(display_names_offset);
+            self.put_u32(data.display_names.len() as u32);
+
+            let slots: Vec<(usize, usize)> = data.display_names.iter()
+                                                 .map(|_| (self.put_placeholder(), self.put_placeholder()))
+                                                 .collect();
+            for ((lang, name), (lang_slot, name_slot)) in data.display_names.iter().zip(slots) {
+                self.set_here(lang_slot);
+                self.put_string(lang);
+                self.set_here(name_slot);
+                self.put_string(name);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use gtk_cache::*;
+    use gtk_icon_cache::GtkIconCache;
+
+    #[test]
+    fn test_icon_name_hash() {
+        assert_eq!(icon_name_hash("deepin-deb-installer"), 1927089920);
+        assert_eq!(icon_name_hash("web-browser"), 2769241519);
+    }
+
+    #[test]
+    fn test_same_as_gtk() {
+        // generated by gtk-update-icon-cache
+        let theme = IconTheme::from_dir("tests/icons/themed").unwrap();
+        assert_eq!(GtkIconCacheWriter::new(&theme).to_bytes().unwrap(),
+                    fs::read("tests/icons/themed/icon-theme.cache").unwrap());
+
+        // `.icon` files
+        let writer = GtkIconCacheWriter::with_theme_dir("tests/gtk-cache/icon-data");
+        assert_eq!(writer.to_bytes().unwrap(), fs::read("tests/gtk-cache/icon-data.cache").unwrap());
+    }
+
+    #[test]
+    fn test_cache_reader() {
+        let theme = IconTheme::from_dir("tests/icons/themed").unwrap();
+        let cache = GtkIconCacheReader::open(Path::new("tests/icons/themed/icon-theme.cache"), theme.directories()).unwrap();
+        let dir = |name| theme.directories().iter().position(|x| x.name() == name).unwrap();
+
+        let mut images = cache.images("test");
+        images.sort();
+        assert_eq!(images, vec![(dir("apps/32"), FLAG_PNG), (dir("apps/48"), FLAG_PNG)]);
+
+        let mut dirs = cache.dirs_of_file("test.png");
+        dirs.sort();
+        assert_eq!(dirs, vec![dir("apps/32"), dir("apps/48")]);
+        assert!(cache.dirs_of_file("test.svg").is_empty());
+        assert!(cache.dirs_of_file("test").is_empty());
+        assert!(cache.images("no-such-icon").is_empty());
+
+        // symbolic PNGs, generated by gtk-update-icon-cache
+        let theme = IconTheme::from_dir("tests/icons/big").unwrap();
+        let cache = GtkIconCacheReader::open(Path::new("tests/icons/big/icon-theme.cache"), theme.directories()).unwrap();
+        let dirs: Vec<&str> = cache.dirs_of_file("gtk3-widget-factory-symbolic.symbolic.png").into_iter()
+                                   .map(|x| theme.directories()[x].name())
+                                   .collect();
+        assert!(!dirs.is_empty());
+        assert!(dirs.iter().all(|x| Path::new("tests/icons/big").join(x).join("gtk3-widget-factory-symbolic.symbolic.png").is_file()));
+    }
+
+    #[test]
+    fn test_broken_image_count() {
+        let theme = IconTheme::from_dir("tests/icons/themed").unwrap();
+        let path = Path::new("target/test-gtk-cache-broken-count.cache");
+        let cache = GtkIconCacheReader::open(Path::new("tests/icons/themed/icon-theme.cache"), theme.directories()).unwrap();
+
+        // image list of `test`
+        let bucket = (icon_name_hash("test") % cache.n_buckets) as usize;
+        let mut icon = cache.card32(cache.hash_offset + 4 + 4 * bucket).unwrap() as usize;
+        while cache.card32(icon + 4).and_then(|x| cache.string(x as usize)) != Some("test") {
+            icon = cache.card32(icon).unwrap() as usize;
+        }
+        let list = cache.card32(icon + 8).unwrap() as usize;
+
+        let mut data = cache.data.clone();
+        data[list..list + 4].copy_from_slice(&[0xff; 4]);
+        fs::write(path, data).unwrap();
+
+        let cache = GtkIconCacheReader::open(path, theme.directories()).unwrap();
+        assert!(cache.images("test").is_empty());
+        assert!(!cache.images("deepin-deb-installer").is_empty());
+    }
+
+    #[test]
+    fn test_read_written_cache() {
+        let path = Path::new("target/test-gtk-cache-writer.cache");
+        let theme = IconTheme::from_dir("tests/icons/big").unwrap();
+        GtkIconCacheWriter::new(&theme).write_to(path).unwrap();
+
+        let ours = GtkIconCache::with_file_path(path).unwrap();
+        let gtk = GtkIconCache::with_file_path("tests/icons/big/icon-theme.cache").unwrap();
+
+        for name in &["fcitx", "wireshark", "filezilla", "gtk3-widget-factory-symbolic.symbolic", "nm-device-wwan"] {
+            let mut dirs = ours.lookup(name).unwrap();
+            let mut expected = gtk.lookup(name).unwrap();
+            dirs.sort();
+            expected.sort();
+
+            assert_eq!(dirs, expected);
+        }
+        assert_eq!(ours.lookup("no-such-icon"), None);
+    }
+}
@@ -0,0 +1,116 @@
+use icon_theme::IconDirectory;
+
+use std::path::{Path, PathBuf};
+
+///
+/// File format of a found icon.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IconFormat {
+    Png,
+    Svg,
+    Xpm,
+}
+
+impl IconFormat {
+    pub fn from_path<T: AsRef<Path>>(path: T) -> Option<Self> {
+        match path.as_ref().extension().and_then(|x| x.to_str()) {
+            Some("png") => Some(IconFormat::Png),
+            Some("svg") => Some(IconFormat::Svg),
+            Some("xpm") => Some(IconFormat::Xpm),
+            _ => None,
+        }
+    }
+}
+
+///
+/// A found icon and where it comes from.
+///
+#[derive(Debug, Clone)]
+pub struct IconInfo {
+    path: PathBuf,
+    format: IconFormat,
+    theme_name: Option<String>,
+    directory: Option<IconDirectory>,
+    exact_size: bool,
+    matched_name: String,
+    fallback: bool,
+    residual_scale: f64,
+}
+
+impl IconInfo {
+    pub(crate) fn new<T: Into<PathBuf>, N: AsRef<str>>(path: T, matched_name: N) -> Self {
+        let path = path.into();
+
+        Self {
+            format: IconFormat::from_path(&path).unwrap_or(IconFormat::Png),
+            path,
+            theme_name: None,
+            directory: None,
+            exact_size: false,
+            matched_name: matched_name.as_ref().to_string(),
+            fallback: false,
+            residual_scale: 1.0,
+        }
+    }
+
+    pub(crate) fn with_directory<T: AsRef<str>>(mut self, theme_name: T, directory: &IconDirectory, exact_size: bool) -> Self {
+        self.theme_name = Some(theme_name.as_ref().to_string());
+        self.directory = Some(directory.clone());
+        self.exact_size = exact_size;
+        self
+    }
+
+    pub(crate) fn set_fallback(&mut self, fallback: bool) {
+        self.fallback = fallback;
+    }
+
+    pub(crate) fn set_residual_scale(&mut self, residual_scale: f64) {
+        self.residual_scale = residual_scale;
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn into_path(self) -> PathBuf {
+        self.path
+    }
+
+    pub fn format(&self) -> IconFormat {
+        self.format
+    }
+
+    /// Internal name of the theme serving the icon, `None` if found in extra dirs.
+    pub fn theme_name(&self) -> Option<&str> {
+        self.theme_name.as_deref()
+    }
+
+    /// Theme directory the icon is found in, `None` if found in extra dirs.
+    pub fn directory(&self) -> Option<&IconDirectory> {
+        self.directory.as_ref()
+    }
+
+    /// The directory matches the requested size, otherwise it's the closest match.
+    pub fn is_exact_size(&self) -> bool {
+        self.exact_size
+    }
+
+    /// The icon name actually found, may differ from the requested name.
+    pub fn matched_name(&self) -> &str {
+        &self.matched_name
+    }
+
+    /// Found by stripping dash-separated segments of the requested name.
+    pub fn is_fallback(&self) -> bool {
+        self.fallback
+    }
+
+    ///
+    /// Factor to scale the image by after looking up a fractional scale,
+    /// `0.625` for 1.25 served from a `@2` directory, `1.0` for integer scales.
+    ///
+    pub fn residual_scale(&self) -> f64 {
+        self.residual_scale
+    }
+}
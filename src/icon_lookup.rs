@@ -1,110 +1,749 @@
-
 use icon_theme::*;
+use icon_name::IconName;
+use icon_info::IconInfo;
+use dir_index;
+use lookup_options::IconLookupOptions;
+use search_path::SearchPath;
+use theme_cache::ThemeCache;
+use theme_info::{self, IconThemeInfo};
+use theme_name::ThemeNameChain;
+use error::IconThemeError;
+#[cfg(feature = "watch")]
+use watcher::ThemeWatcher;
+
+use lru_cache::LruCache;
 
 use std::path::PathBuf;
-use std::process::Command;
 use std::sync::Arc;
-use std::sync::RwLock;
+use std::sync::{Mutex, RwLock};
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 macro_rules! ret_if_found {
     ($value: expr) => {
-        if let Some(path) = $value {
-            debug_assert!(path.is_file());
-            return Some(path)
+        if let Some(icon) = $value {
+            return Some(icon)
         }
     };
 }
 
 lazy_static! {
-    static ref HICOLOR_THEME: Option<Arc<IconTheme>> = IconTheme::from_name("hicolor").ok();
-    static ref DEFAULT_THEME_NAME: RwLock<String> = RwLock::new(get_default_icon_theme_name().unwrap_or("hicolor".to_string()));
-}
-
-fn get_default_icon_theme_name() -> Option<String> {
-    let result = Command::new("gsettings")
-                    .arg("get")
-                    .arg("org.gnome.desktop.interface")
-                    .arg("icon-theme")
-                    .output()
-                    .expect("failed to get default icon theme");
-
-    if result.status.success() {
-        let ref name = result.stdout[1..result.stdout.len() - 2];
-        Some(String::from_utf8_lossy(&name).to_string())
-    } else {
-        None
-    }
+    static ref DEFAULT_LOOKUP: IconLookup = IconLookup::from_env();
 }
 
-pub fn reset_default_theme<T>(theme: T)
-  where T: AsRef<str> {
-    *DEFAULT_THEME_NAME.write().unwrap() = theme.as_ref().to_string();
+/// Key of the lookup result cache.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct ResultKey {
+    theme: String,
+    icon: IconName,
+    size: i32,
+    scale: i32,
+    options: IconLookupOptions,
 }
 
-pub fn find_icon_with_theme_name<T, I>(theme: T, icon: I, size: i32, scale: i32) -> Option<PathBuf>
-  where T: AsRef<str>, I: AsRef<str> {
+///
+/// Snapshot of the lookup result cache counters.
+///
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ResultCacheStats {
+    hits: usize,
+    misses: usize,
+    len: usize,
+    capacity: usize,
+}
 
-    match IconTheme::from_name(theme.as_ref()) {
-        Ok(theme) => find_icon_in_theme(&*theme, icon, size, scale),
-        _ => find_icon(icon, size, scale),
+impl ResultCacheStats {
+    /// Lookups answered from the cache, found or not.
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    /// Lookups which walked the themes.
+    pub fn misses(&self) -> usize {
+        self.misses
+    }
+
+    /// Cached results, including icons not found.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
     }
 }
 
-pub fn find_icon_in_theme<T>(theme: &IconTheme, icon: T, size: i32, scale: i32) -> Option<PathBuf>
-  where T: AsRef<str> {
+///
+/// Icon lookup context.
+///
+/// Owns the search dirs, the default theme, the fallback theme and a cache of
+/// loaded themes, so different components in one process can look up icons
+/// with different settings.
+///
+/// ```no_run
+/// use themed_icon_lookup::icon_lookup::IconLookup;
+///
+/// let lookup = IconLookup::new(vec!["/usr/share/icons"])
+///                 .with_default_theme("Adwaita");
+///
+/// let icon = lookup.find_icon("firefox", 48, 1);
+/// ```
+///
+#[derive(Debug)]
+pub struct IconLookup {
+    base_dirs: Vec<PathBuf>,
+    extra_dirs: Vec<PathBuf>,
+    default_theme: RwLock<String>,
+    fallback_theme: String,
+    dir_index: bool,
+    index_cache_dir: Option<PathBuf>,
+    theme_cache: ThemeCache,
+    result_cache: Mutex<LruCache<ResultKey, Option<IconInfo>>>,
+    result_hits: AtomicUsize,
+    result_misses: AtomicUsize,
+    /// bumped by clearing the result cache, lookups started before are not cached
+    result_generation: AtomicUsize,
+    #[cfg(feature = "watch")]
+    watcher: ThemeWatcher,
+}
 
-    let icon = &icon.into();
+impl IconLookup {
+    ///
+    /// Create a lookup context searching themes in `base_dirs`.
+    ///
+    /// * `base_dirs` - Directories containing icon themes, e.g. `/usr/share/icons`.
+    ///
+    pub fn new<T: Into<PathBuf>>(base_dirs: Vec<T>) -> Self {
 
-    ret_if_found!(theme.lookup_icon(icon, size, scale));
+        let mut dirs: Vec<PathBuf> = vec![];
+        for dir in base_dirs.into_iter().map(|x| x.into()) {
+            if !dirs.contains(&dir) {
+                dirs.push(dir);
+            }
+        }
 
-    // find in parents
-    for parent in theme.parents() {
-        if let Ok(parent_theme) = IconTheme::from_name(parent) {
-            ret_if_found!(parent_theme.lookup_icon(icon, size, scale));
+        Self {
+            base_dirs: dirs,
+            extra_dirs: vec![],
+            default_theme: RwLock::new("hicolor".to_string()),
+            fallback_theme: "hicolor".to_string(),
+            dir_index: false,
+            index_cache_dir: None,
+            theme_cache: ThemeCache::new(8),
+            result_cache: Mutex::new(LruCache::new(0)),
+            result_hits: AtomicUsize::new(0),
+            result_misses: AtomicUsize::new(0),
+            result_generation: AtomicUsize::new(0),
+            #[cfg(feature = "watch")]
+            watcher: ThemeWatcher::default(),
         }
     }
 
-    // find in hicolor
-    if let Some(ref hicolor) = *HICOLOR_THEME {
-        ret_if_found!(hicolor.lookup_icon(icon, size, scale));
+    /// Create a lookup context searching themes in a resolved `SearchPath`.
+    pub fn from_search_path(search_path: &SearchPath) -> Self {
+        Self::new(search_path.base_dirs().to_vec())
+            .with_extra_dirs(search_path.extra_dirs().to_vec())
+    }
+
+    fn from_env() -> Self {
+        // tests don't depend on the desktop settings of the host
+        if cfg!(test) {
+            return Self::new(vec!["tests/icons"]).with_default_theme("hicolor");
+        }
+
+        Self::from_search_path(&SearchPath::from_env())
+            .with_default_theme(ThemeNameChain::from_env().theme_name())
+    }
+
+    /// Directories without theme structure searched at last, e.g. `/usr/share/pixmaps`.
+    pub fn with_extra_dirs<T: Into<PathBuf>>(mut self, extra_dirs: Vec<T>) -> Self {
+        self.extra_dirs = extra_dirs.into_iter().map(|x| x.into()).collect();
+        self
+    }
+
+    pub fn with_default_theme<T: AsRef<str>>(self, theme: T) -> Self {
+        self.reset_default_theme(theme);
+        self
+    }
+
+    /// Theme searched after the inheritance chain, `hicolor` by default.
+    pub fn with_fallback_theme<T: AsRef<str>>(mut self, theme: T) -> Self {
+        self.fallback_theme = theme.as_ref().to_string();
+        self
+    }
+
+    /// Index icon files of loaded themes in memory, see `IconTheme::with_dir_index`.
+    pub fn with_dir_index(mut self, enable: bool) -> Self {
+        self.dir_index = enable;
+        self
+    }
+
+    ///
+    /// Save the in-memory index of themes without `icon-theme.cache` in
+    /// `$XDG_CACHE_HOME/themed-icon-lookup`, so later processes load it instead of
+    /// probing files, see `IconTheme::with_persistent_index`.
+    ///
+    pub fn with_persistent_index(mut self, enable: bool) -> Self {
+        self.index_cache_dir = if enable { dir_index::default_cache_dir() } else { None };
+        self
+    }
+
+    /// Like `with_persistent_index`, but save indexes in `cache_dir`.
+    pub fn with_index_cache_dir<T: Into<PathBuf>>(mut self, cache_dir: T) -> Self {
+        self.index_cache_dir = Some(cache_dir.into());
+        self
+    }
+
+    /// Cache up to `capacity` loaded themes, 8 by default.
+    pub fn with_theme_cache_capacity(self, capacity: usize) -> Self {
+        self.set_theme_cache_capacity(capacity);
+        self
+    }
+
+    /// Least recently used themes are dropped if more than `capacity` themes are loaded.
+    pub fn set_theme_cache_capacity(&self, capacity: usize) {
+        self.theme_cache.set_capacity(capacity);
+    }
+
+    pub fn theme_cache_capacity(&self) -> usize {
+        self.theme_cache.capacity()
+    }
+
+    /// Internal names of cached themes, most recently used first.
+    pub fn cached_themes(&self) -> Vec<String> {
+        self.theme_cache.themes().into_iter().map(|x| x.0).collect()
+    }
+
+    ///
+    /// Drop a cached theme so it's loaded again on next use, returns whether it
+    /// was cached. Lookup results are dropped too, they may depend on the theme.
+    ///
+    pub fn purge_theme<T: AsRef<str>>(&self, theme: T) -> bool {
+        let r = self.theme_cache.remove(theme.as_ref());
+        self.clear_result_cache();
+
+        #[cfg(feature = "watch")]
+        self.watcher.unwatch_theme(theme.as_ref());
+
+        r
+    }
+
+    /// Drop all cached themes and lookup results.
+    pub fn purge_themes(&self) {
+        self.theme_cache.clear();
+        self.clear_result_cache();
+
+        #[cfg(feature = "watch")]
+        self.watcher.unwatch_themes();
+    }
+
+    /// Cache up to `capacity` lookup results, see `set_result_cache_capacity`.
+    pub fn with_result_cache(self, capacity: usize) -> Self {
+        self.set_result_cache_capacity(capacity);
+        self
+    }
+
+    ///
+    /// Cache results of lookups by theme name, including icons not found, so
+    /// repeated lookups don't walk the themes again. 0 disables the cache, the default.
+    ///
+    /// Results are keyed on (theme, icon, size, scale, options) and dropped with
+    /// the theme cache, e.g. when the watcher reports a change or by
+    /// `invalidate_dir_index`. Without a watcher, icons installed later are not
+    /// seen until then.
+    ///
+    pub fn set_result_cache_capacity(&self, capacity: usize) {
+        self.result_cache.lock().unwrap().set_capacity(capacity);
+    }
+
+    pub fn result_cache_stats(&self) -> ResultCacheStats {
+        let cache = self.result_cache.lock().unwrap();
+
+        ResultCacheStats {
+            hits: self.result_hits.load(Ordering::Relaxed),
+            misses: self.result_misses.load(Ordering::Relaxed),
+            len: cache.len(),
+            capacity: cache.capacity(),
+        }
+    }
+
+    /// Drop all cached lookup results, the counters are kept.
+    pub fn clear_result_cache(&self) {
+        let mut cache = self.result_cache.lock().unwrap();
+        self.result_generation.fetch_add(1, Ordering::SeqCst);
+        cache.clear();
+    }
+
+    /// Watch themes for changes, see `enable_watcher`.
+    #[cfg(feature = "watch")]
+    pub fn with_watcher(self, enable: bool) -> Self {
+        self.enable_watcher(enable);
+        self
+    }
+
+    ///
+    /// Watch base dirs and loaded themes with inotify, themes changed on disk are
+    /// reloaded on next lookup instead of being cached until restart.
+    ///
+    /// Returns whether watching is enabled, it fails if inotify is unavailable.
+    ///
+    #[cfg(feature = "watch")]
+    pub fn enable_watcher(&self, enable: bool) -> bool {
+        let themes: Vec<Arc<IconTheme>> = self.theme_cache.themes().into_iter().map(|x| x.1).collect();
+
+        if let Err(e) = self.watcher.enable(enable, &self.base_dirs, themes.iter().map(|x| &**x)) {
+            warn!("failed to watch icon themes: {}", e);
+        }
+
+        self.watcher.is_enabled()
+    }
+
+    ///
+    /// Call `callback` with the internal name of each theme changed on disk, from
+    /// the watcher thread and only while the watcher is enabled.
+    ///
+    /// Newly installed or removed themes are reported too, not only loaded ones.
+    ///
+    #[cfg(feature = "watch")]
+    pub fn on_theme_changed<F>(&self, callback: F)
+      where F: Fn(&str) + Send + Sync + 'static {
+        self.watcher.connect(callback);
+    }
+
+    pub fn base_dirs(&self) -> &[PathBuf] {
+        &self.base_dirs
+    }
+
+    pub fn extra_dirs(&self) -> &[PathBuf] {
+        &self.extra_dirs
+    }
+
+    pub fn default_theme(&self) -> String {
+        self.default_theme.read().unwrap().clone()
+    }
+
+    pub fn reset_default_theme<T: AsRef<str>>(&self, theme: T) {
+        *self.default_theme.write().unwrap() = theme.as_ref().to_string();
+    }
+
+    ///
+    /// Load a theme by name, loaded themes are cached.
+    ///
+    /// The theme is parsed without blocking lookups of other themes, and
+    /// threads asking for a theme being loaded wait for that load.
+    ///
+    pub fn theme<T: AsRef<str>>(&self, name: T) -> Result<Arc<IconTheme>, IconThemeError> {
+
+        let name = name.as_ref();
+
+        #[cfg(feature = "watch")]
+        {
+            if let Some(changes) = self.watcher.take_changes() {
+                self.clear_result_cache();
+
+                if changes.all {
+                    self.theme_cache.clear();
+                }
+
+                for name in changes.themes.iter() {
+                    self.theme_cache.remove(name);
+                }
+            }
+        }
+
+        self.theme_cache.get_or_load(name, || {
+            let theme = IconTheme::from_name_in_dirs(name, &self.base_dirs, &self.extra_dirs)?;
+            let theme = match self.index_cache_dir {
+                Some(ref dir) if !theme.has_gtk_cache() => theme.with_persistent_index(dir),
+                _ => theme.with_dir_index(self.dir_index),
+            };
+
+            #[cfg(feature = "watch")]
+            self.watcher.watch_theme(&theme);
+
+            Ok(theme)
+        })
+    }
+
+    /// Themes installed in the base dirs, see `IconThemeInfo`.
+    pub fn list_icon_themes(&self) -> Vec<IconThemeInfo> {
+        theme_info::list_icon_themes_in_dirs(&self.base_dirs)
+    }
+
+    /// Drop the in-memory index of all loaded themes.
+    pub fn invalidate_dir_index(&self) {
+        for (_, theme) in self.theme_cache.themes() {
+            theme.invalidate_dir_index();
+        }
+
+        self.clear_result_cache();
+    }
+
+    /// Themes `theme` inherits from, loaded through this context.
+    pub fn inheritance_chain(&self, theme: &IconTheme) -> Vec<Arc<IconTheme>> {
+        theme.inheritance_chain_with(&|name: &str| self.theme(name))
+    }
+
+    pub fn find_icon<I>(&self, icon: I, size: i32, scale: i32) -> Option<PathBuf>
+      where I: Into<IconName> {
+
+        self.find_icon_info(icon, size, scale, &IconLookupOptions::default()).map(|x| x.into_path())
+    }
+
+    pub fn find_icon_info<I>(&self, icon: I, size: i32, scale: i32, options: &IconLookupOptions) -> Option<IconInfo>
+      where I: Into<IconName> {
+
+        let theme = self.default_theme();
+
+        self.find_icon_info_with_theme_name(theme, icon, size, scale, options)
+    }
+
+    pub fn find_icon_with_theme_name<T, I>(&self, theme: T, icon: I, size: i32, scale: i32) -> Option<PathBuf>
+      where T: AsRef<str>, I: Into<IconName> {
+
+        self.find_icon_info_with_theme_name(theme, icon, size, scale, &IconLookupOptions::default()).map(|x| x.into_path())
+    }
+
+    pub fn find_icon_info_with_theme_name<T, I>(&self, theme: T, icon: I, size: i32, scale: i32, options: &IconLookupOptions) -> Option<IconInfo>
+      where T: AsRef<str>, I: Into<IconName> {
+
+        let icon = icon.into();
+
+        match self.try_find_icon_info_with_theme_name(theme.as_ref(), icon.clone(), size, scale, options) {
+            Ok(r) => r,
+            Err(e) => {
+                let default_theme = self.default_theme();
+
+                if theme.as_ref() != default_theme {
+                    warn!("{}, fallback to default theme", e);
+                    return self.find_icon_info_with_theme_name(default_theme, icon, size, scale, options);
+                }
+
+                if options.is_no_fallback_theme() {
+                    warn!("{}", e);
+                    return None;
+                }
+
+                warn!("{}, fallback to {}", e, self.fallback_theme);
+                self.theme(&self.fallback_theme)
+                    .ok()
+                    .and_then(|x| self.find_icon_info_in_theme(&x, icon, size, scale, options))
+            },
+        }
+    }
+
+    /// Look up with a fractional scale like 1.25, see `resolve_fractional_scale`.
+    pub fn find_icon_info_fractional<I>(&self, icon: I, size: i32, scale: f64, options: &IconLookupOptions) -> Option<IconInfo>
+      where I: Into<IconName> {
+
+        let theme = self.default_theme();
+
+        self.find_icon_info_fractional_with_theme_name(theme, icon, size, scale, options)
+    }
+
+    pub fn find_icon_info_fractional_with_theme_name<T, I>(&self, theme: T, icon: I, size: i32, scale: f64, options: &IconLookupOptions) -> Option<IconInfo>
+      where T: AsRef<str>, I: Into<IconName> {
+
+        let (scale, residual_scale) = resolve_fractional_scale(scale);
+
+        self.find_icon_info_with_theme_name(theme, icon, size, scale, options).map(|mut x| {
+            x.set_residual_scale(residual_scale);
+            x
+        })
+    }
+
+    /// Like `find_icon_with_theme_name`, but report why the theme can't be loaded
+    /// instead of falling back to the default theme.
+    pub fn try_find_icon_with_theme_name<T, I>(&self, theme: T, icon: I, size: i32, scale: i32) -> Result<Option<PathBuf>, IconThemeError>
+      where T: AsRef<str>, I: Into<IconName> {
+
+        self.try_find_icon_info_with_theme_name(theme, icon, size, scale, &IconLookupOptions::default())
+            .map(|x| x.map(|x| x.into_path()))
+    }
+
+    fn try_find_icon_info_with_theme_name<T, I>(&self, theme: T, icon: I, size: i32, scale: i32, options: &IconLookupOptions) -> Result<Option<IconInfo>, IconThemeError>
+      where T: AsRef<str>, I: Into<IconName> {
+
+        let theme_name = theme.as_ref();
+        let theme = self.theme(theme_name)?;
+
+        if self.result_cache.lock().unwrap().capacity() == 0 {
+            return Ok(self.find_icon_info_in_theme(&theme, icon, size, scale, options));
+        }
+
+        let key = ResultKey {
+            theme: theme_name.to_string(),
+            icon: icon.into(),
+            size,
+            scale,
+            options: *options,
+        };
+
+        let generation = {
+            let mut cache = self.result_cache.lock().unwrap();
+            if let Some(r) = cache.get_mut(&key) {
+                self.result_hits.fetch_add(1, Ordering::Relaxed);
+                return Ok(r.clone());
+            }
+
+            self.result_generation.load(Ordering::SeqCst)
+        };
+
+        // walk the themes outside of the lock
+        self.result_misses.fetch_add(1, Ordering::Relaxed);
+        let r = self.find_icon_info_in_theme(&theme, key.icon.clone(), size, scale, options);
+
+        // the result may be stale if the cache was cleared meanwhile
+        let mut cache = self.result_cache.lock().unwrap();
+        if generation == self.result_generation.load(Ordering::SeqCst) {
+            cache.insert(key, r.clone());
+        }
+
+        Ok(r)
+    }
+
+    pub fn find_icon_in_theme<T>(&self, theme: &IconTheme, icon: T, size: i32, scale: i32) -> Option<PathBuf>
+      where T: Into<IconName> {
+
+        self.find_icon_info_in_theme(theme, icon, size, scale, &IconLookupOptions::default()).map(|x| x.into_path())
+    }
+
+    pub fn find_icon_info_in_theme<T>(&self, theme: &IconTheme, icon: T, size: i32, scale: i32, options: &IconLookupOptions) -> Option<IconInfo>
+      where T: Into<IconName> {
+
+        let icon = &icon.into();
+        let mut searched = vec![];
+
+        ret_if_found!(self.find_in_chain(theme, options, &mut searched, |x| x.lookup_icon_info(icon, size, scale, options)));
+
+        // fallback
+        ret_if_found!(theme.lookup_fallback_icon_info(icon, size, scale, options));
+
+        // fallback in parents and fallback theme
+        for x in &searched {
+            ret_if_found!(x.lookup_fallback_icon_info(icon, size, scale, options));
+        }
+
+        None
+    }
+
+    pub fn find_best_icon<T>(&self, theme: &IconTheme, icons: &[T], size: i32, scale: i32) -> Option<PathBuf>
+      where T: Clone + Into<IconName> {
+
+        self.find_best_icon_info(theme, icons, size, scale, &IconLookupOptions::default()).map(|x| x.into_path())
+    }
+
+    ///
+    /// FindBestIcon of the icon theme spec, look up a prioritized list of names.
+    ///
+    /// Every name is tried in a theme before moving to the next theme of the chain,
+    /// then in extra dirs, and dash-separated segments are stripped only after all
+    /// names fail. Stripped names are searched in each theme and then in extra dirs
+    /// like `find_icon_info_in_theme` does.
+    ///
+    pub fn find_best_icon_info<T>(&self, theme: &IconTheme, icons: &[T], size: i32, scale: i32, options: &IconLookupOptions) -> Option<IconInfo>
+      where T: Clone + Into<IconName> {
+
+        let icons: Vec<IconName> = icons.iter().cloned().map(|x| x.into()).collect();
+
+        // extra dirs are searched once after all themes
+        let theme_options = options.no_extra_dirs(true);
+        let mut searched = vec![];
+
+        ret_if_found!(self.find_in_chain(theme, options, &mut searched, |x| {
+            icons.iter().filter_map(|icon| x.lookup_icon_info(icon, size, scale, &theme_options)).next()
+        }));
+
+        for icon in icons.iter() {
+            ret_if_found!(theme.lookup_extra_icon_info(&options.apply(icon), options));
+        }
+
+        // stripped names are looked up in extra dirs too, like `find_icon_info_in_theme`
+        for x in Some(theme).into_iter().chain(searched.iter().map(|x| &**x)) {
+            for icon in icons.iter() {
+                ret_if_found!(x.lookup_fallback_icon_info(icon, size, scale, options));
+            }
+        }
+
+        None
     }
 
-    // fallback
-    ret_if_found!(theme.lookup_fallback_icon(icon, size, scale));
+    ///
+    /// Search `theme`, its parents depth-first and then the fallback theme until
+    /// `find` returns an icon. Parents are loaded only when the search reaches
+    /// them, themes searched after `theme` are pushed to `searched` for later passes.
+    ///
+    fn find_in_chain<F>(&self, theme: &IconTheme, options: &IconLookupOptions, searched: &mut Vec<Arc<IconTheme>>, find: F) -> Option<IconInfo>
+      where F: Fn(&IconTheme) -> Option<IconInfo> {
+
+        ret_if_found!(find(theme));
 
-    // fallback in parents
-    for parent in theme.parents() {
-        if let Ok(parent_theme) = IconTheme::from_name(parent) {
-            ret_if_found!(parent_theme.lookup_fallback_icon(icon, size, scale));
+        let mut visited = vec![theme.internal_name().to_string()];
+        ret_if_found!(theme.find_in_parents(&|name: &str| self.theme(name), &mut visited, &mut |parent| {
+            searched.push(parent.clone());
+            find(parent)
+        }));
+
+        // the fallback theme, if it's not one of the parents
+        if options.is_no_fallback_theme() || visited.contains(&self.fallback_theme) {
+            return None;
         }
+
+        let fallback_theme = self.theme(&self.fallback_theme).ok()?;
+        searched.push(fallback_theme.clone());
+
+        find(&fallback_theme)
     }
+}
 
-    // fallback in hicolor
-    if let Some(ref hicolor) = *HICOLOR_THEME {
-        ret_if_found!(hicolor.lookup_fallback_icon(icon, size, scale));
+///
+/// Split a fractional scale into the integer scale of directories to look up and
+/// the residual factor to scale the found image by.
+///
+/// The scale is rounded up as recommended by the icon theme spec, downscaling a
+/// larger image looks better than upscaling a smaller one, so 1.25 is looked up
+/// as `@2` in `size * 2` effective pixels with a residual of 0.625.
+/// Invalid scales are treated as 1.
+///
+pub fn resolve_fractional_scale(scale: f64) -> (i32, f64) {
+    if !scale.is_finite() || scale <= 0.0 {
+        return (1, 1.0);
     }
 
-    None
+    // tolerate rounding errors like 2.0000001
+    let integer = ((scale - 1e-6).ceil() as i32).max(1);
+
+    (integer, scale / f64::from(integer))
+}
+
+/// The lookup context used by the free functions of this module.
+pub fn default_lookup() -> &'static IconLookup {
+    &DEFAULT_LOOKUP
+}
+
+pub fn reset_default_theme<T>(theme: T)
+  where T: AsRef<str> {
+    DEFAULT_LOOKUP.reset_default_theme(theme);
+}
+
+pub fn list_icon_themes() -> Vec<IconThemeInfo> {
+    DEFAULT_LOOKUP.list_icon_themes()
+}
+
+pub fn set_theme_cache_capacity(capacity: usize) {
+    DEFAULT_LOOKUP.set_theme_cache_capacity(capacity);
+}
+
+pub fn theme_cache_capacity() -> usize {
+    DEFAULT_LOOKUP.theme_cache_capacity()
+}
+
+pub fn cached_themes() -> Vec<String> {
+    DEFAULT_LOOKUP.cached_themes()
+}
+
+pub fn purge_theme<T>(theme: T) -> bool
+  where T: AsRef<str> {
+    DEFAULT_LOOKUP.purge_theme(theme)
+}
+
+pub fn purge_themes() {
+    DEFAULT_LOOKUP.purge_themes();
+}
+
+#[cfg(feature = "watch")]
+pub fn enable_watcher(enable: bool) -> bool {
+    DEFAULT_LOOKUP.enable_watcher(enable)
+}
+
+#[cfg(feature = "watch")]
+pub fn on_theme_changed<F>(callback: F)
+  where F: Fn(&str) + Send + Sync + 'static {
+    DEFAULT_LOOKUP.on_theme_changed(callback);
+}
+
+pub fn find_icon_with_theme_name<T, I>(theme: T, icon: I, size: i32, scale: i32) -> Option<PathBuf>
+  where T: AsRef<str>, I: Into<IconName> {
+
+    DEFAULT_LOOKUP.find_icon_with_theme_name(theme, icon, size, scale)
+}
+
+/// Like `find_icon_with_theme_name`, but report why the theme can't be loaded
+/// instead of falling back to the default theme.
+pub fn try_find_icon_with_theme_name<T, I>(theme: T, icon: I, size: i32, scale: i32) -> Result<Option<PathBuf>, IconThemeError>
+  where T: AsRef<str>, I: Into<IconName> {
+
+    DEFAULT_LOOKUP.try_find_icon_with_theme_name(theme, icon, size, scale)
+}
+
+pub fn find_icon_in_theme<T>(theme: &IconTheme, icon: T, size: i32, scale: i32) -> Option<PathBuf>
+  where T: Into<IconName> {
+
+    DEFAULT_LOOKUP.find_icon_in_theme(theme, icon, size, scale)
 }
 
 pub fn find_icon<I>(icon: I, size: i32, scale: i32) -> Option<PathBuf>
-  where I: AsRef<str> {
+  where I: Into<IconName> {
+
+    DEFAULT_LOOKUP.find_icon(icon, size, scale)
+}
+
+pub fn find_icon_info<I>(icon: I, size: i32, scale: i32, options: &IconLookupOptions) -> Option<IconInfo>
+  where I: Into<IconName> {
+
+    DEFAULT_LOOKUP.find_icon_info(icon, size, scale, options)
+}
+
+pub fn find_icon_info_with_theme_name<T, I>(theme: T, icon: I, size: i32, scale: i32, options: &IconLookupOptions) -> Option<IconInfo>
+  where T: AsRef<str>, I: Into<IconName> {
+
+    DEFAULT_LOOKUP.find_icon_info_with_theme_name(theme, icon, size, scale, options)
+}
+
+pub fn find_icon_info_in_theme<T>(theme: &IconTheme, icon: T, size: i32, scale: i32, options: &IconLookupOptions) -> Option<IconInfo>
+  where T: Into<IconName> {
+
+    DEFAULT_LOOKUP.find_icon_info_in_theme(theme, icon, size, scale, options)
+}
+
+pub fn find_icon_info_fractional<I>(icon: I, size: i32, scale: f64, options: &IconLookupOptions) -> Option<IconInfo>
+  where I: Into<IconName> {
+
+    DEFAULT_LOOKUP.find_icon_info_fractional(icon, size, scale, options)
+}
+
+pub fn find_icon_info_fractional_with_theme_name<T, I>(theme: T, icon: I, size: i32, scale: f64, options: &IconLookupOptions) -> Option<IconInfo>
+  where T: AsRef<str>, I: Into<IconName> {
+
+    DEFAULT_LOOKUP.find_icon_info_fractional_with_theme_name(theme, icon, size, scale, options)
+}
+
+pub fn find_best_icon<T>(theme: &IconTheme, icons: &[T], size: i32, scale: i32) -> Option<PathBuf>
+  where T: Clone + Into<IconName> {
+
+    DEFAULT_LOOKUP.find_best_icon(theme, icons, size, scale)
+}
+
+pub fn find_best_icon_info<T>(theme: &IconTheme, icons: &[T], size: i32, scale: i32, options: &IconLookupOptions) -> Option<IconInfo>
+  where T: Clone + Into<IconName> {
 
-    lookup!(&*DEFAULT_THEME_NAME.read().unwrap(), icon, size, scale)
+    DEFAULT_LOOKUP.find_best_icon_info(theme, icons, size, scale, options)
 }
 
 #[cfg(test)]
 mod test {
     use icon_lookup::*;
+    use icon_name::FallbackRules;
+    use icon_info::IconFormat;
+    use lookup_options::IconLookupOptions;
 
-    use std::env;
+    use std::path::Path;
 
     #[test]
     fn test_find_fixed() {
-        let _env_lock = TEST_ENV_MUTEX.lock().unwrap();
-
-        env::set_var("XDG_DATA_DIRS", "tests");
-
         test_lookup!("themed", "deepin-deb-installer", 16, 1
                     => "tests/icons/themed/apps/16/deepin-deb-installer.svg");
         test_lookup!("themed", "deepin-deb-installer", 32, 1
@@ -119,13 +758,348 @@ mod test {
 
     #[test]
     fn test_invalid_theme_name() {
-        let _env_lock = TEST_ENV_MUTEX.lock().unwrap();
-
-        env::set_var("XDG_DATA_DIRS", "tests");
-
         // should be fallback to hicolor
         assert_eq!(Some("tests/icons/hicolor/apps/16/TestAppIcon.png".into()),
                     find_icon_with_theme_name("InvalidThemeName", "TestAppIcon", 16, 1));
+
+        match try_find_icon_with_theme_name("InvalidThemeName", "TestAppIcon", 16, 1) {
+            Err(IconThemeError::ThemeNotFound(name)) => assert_eq!(name, "InvalidThemeName"),
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_symbolic_lookup() {
+        let theme = IconTheme::from_dir("tests/icons/symbolic").unwrap();
+
+        // symbolic names fall back to symbolic icons first
+        test_lookup!(theme, "edit-find-replace-symbolic", 16, 1
+                    => "tests/icons/symbolic/actions/16/edit-symbolic.svg");
+
+        // full-color icons are accepted by default
+        test_lookup!(theme, "go-up-symbolic", 16, 1
+                    => "tests/icons/symbolic/actions/16/go-up.png");
+        assert_eq!(find_icon_in_theme(&theme, IconName::with_rules("go-up-symbolic", FallbackRules::SYMBOLIC), 16, 1),
+                    None);
+
+        // `<name>-symbolic.symbolic.png` files, as named by GTK
+        test_lookup!(theme, "media-playback-start-symbolic", 16, 1
+                    => "tests/icons/symbolic/actions/16/media-playback-start-symbolic.symbolic.png");
+        test_lookup!("big", "gtk3-demo-symbolic", 48, 1
+                    => "tests/icons/big/48x48/apps/gtk3-demo-symbolic.symbolic.png");
+    }
+
+    #[test]
+    fn test_in_another_base_dir() {
+        let lookup = IconLookup::new(vec!["tests/icons", "tests/fake_home/.local/share/icons"]);
+
+        assert_eq!(lookup.find_icon_with_theme_name("themed", "just-in-another-base", 16, 1),
+                    Some("tests/fake_home/.local/share/icons/themed/apps/16/just-in-another-base.png".into()));
+    }
+
+    #[test]
+    fn test_result_cache() {
+        let lookup = IconLookup::new(vec!["tests/icons"]).with_result_cache(2);
+
+        assert_eq!(lookup.find_icon_with_theme_name("themed", "no-such-icon", 48, 1), None);
+        assert_eq!(lookup.find_icon_with_theme_name("themed", "no-such-icon", 48, 1), None);
+        assert_eq!(lookup.find_icon_with_theme_name("themed", "test", 48, 1),
+                    Some("tests/icons/themed/apps/48/test.png".into()));
+        assert_eq!(lookup.find_icon_with_theme_name("themed", "test", 48, 1),
+                    Some("tests/icons/themed/apps/48/test.png".into()));
+
+        let stats = lookup.result_cache_stats();
+        assert_eq!((stats.hits(), stats.misses(), stats.len(), stats.capacity()), (2, 2, 2, 2));
+
+        // options are part of the key
+        let options = IconLookupOptions::default().no_svg(true);
+        assert!(lookup.find_icon_info_with_theme_name("themed", "test", 48, 1, &options).is_some());
+        assert_eq!(lookup.result_cache_stats().misses(), 3);
+        assert_eq!(lookup.result_cache_stats().len(), 2);
+
+        // dropped with the themes
+        lookup.invalidate_dir_index();
+        assert!(lookup.result_cache_stats().is_empty());
+
+        // disabled by default
+        let lookup = IconLookup::new(vec!["tests/icons"]);
+        assert_eq!(lookup.find_icon_with_theme_name("themed", "no-such-icon", 48, 1), None);
+        assert_eq!(lookup.result_cache_stats(), ResultCacheStats::default());
+    }
+
+    #[test]
+    fn test_result_cache_cleared_while_looking_up() {
+        use std::sync::mpsc;
+        use std::thread;
+
+        let lookup = Arc::new(IconLookup::new(vec!["tests/icons"]).with_result_cache(8));
+        let _ = lookup.theme("themed").unwrap();
+
+        // block the load of `parent`, the missing parent of `themed`
+        let (started_tx, started_rx) = mpsc::channel();
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+        let loader = {
+            let lookup = lookup.clone();
+            thread::spawn(move || lookup.theme_cache.get_or_load("parent", move || {
+                started_tx.send(()).unwrap();
+                release_rx.recv().unwrap();
+                Err(IconThemeError::ThemeNotFound("parent".to_string()))
+            }))
+        };
+        started_rx.recv().unwrap();
+
+        let finder = {
+            let lookup = lookup.clone();
+            thread::spawn(move || lookup.find_icon_with_theme_name("themed", "no-such-icon", 48, 1))
+        };
+        while lookup.theme_cache.waiters("parent") == 0 {
+            thread::yield_now();
+        }
+
+        // e.g. the icon is installed while the lookup walks the chain
+        lookup.purge_theme("themed");
+        release_tx.send(()).unwrap();
+
+        assert!(loader.join().unwrap().is_err());
+        assert_eq!(finder.join().unwrap(), None);
+        assert!(lookup.result_cache_stats().is_empty());
+    }
+
+    #[test]
+    fn test_icon_theme_lru_cache() {
+        let lookup = IconLookup::new(vec!["tests/icons"]).with_theme_cache_capacity(1);
+
+        assert_eq!(1, lookup.theme_cache_capacity());
+        assert!(lookup.cached_themes().is_empty());
+
+        assert_eq!(lookup.find_icon_with_theme_name("themed", "test", 48, 1),
+                    Some("tests/icons/themed/apps/48/test.png".into()));
+        // cache should have 1 new item.
+        assert_eq!(1, lookup.cached_themes().len());
+
+        let _ = lookup.find_icon_with_theme_name("hicolor", "test", 48, 1);
+        assert_eq!(lookup.cached_themes(), vec!["hicolor"]);
+    }
+
+    #[test]
+    fn test_should_not_save_invalid_theme() {
+        let lookup = IconLookup::new(vec!["tests/icons"]).with_theme_cache_capacity(1);
+
+        assert!(lookup.cached_themes().is_empty());
+
+        // invalid icon theme should't save
+        assert_eq!(lookup.find_icon_with_theme_name("InvalidThemeName", "TestAppIcon", 16, 1),
+                    Some("tests/icons/hicolor/apps/16/TestAppIcon.png".into()));
+        assert!(!lookup.cached_themes().contains(&"InvalidThemeName".to_string()));
+
+        lookup.purge_themes();
+
+        // valid icon theme should be saved
+        assert_eq!(lookup.find_icon_with_theme_name("hicolor", "TestAppIcon", 16, 1),
+                    Some("tests/icons/hicolor/apps/16/TestAppIcon.png".into()));
+        assert!(lookup.cached_themes().contains(&"hicolor".to_string()));
+    }
+
+    #[test]
+    fn test_purge_themes() {
+        let lookup = IconLookup::new(vec!["tests/icons"]).with_result_cache(8);
+
+        let _ = lookup.find_icon_with_theme_name("themed", "test", 48, 1);
+        // found in `themed`, its parents and the fallback theme are not loaded
+        assert_eq!(lookup.cached_themes(), vec!["themed"]);
+
+        assert!(lookup.purge_theme("themed"));
+        assert!(!lookup.purge_theme("themed"));
+        assert!(!lookup.cached_themes().contains(&"themed".to_string()));
+        assert!(lookup.result_cache_stats().is_empty());
+
+        lookup.purge_themes();
+        lookup.set_theme_cache_capacity(2);
+        for theme in &["themed", "chain-a", "hidpi"] {
+            let _ = lookup.theme(theme).unwrap();
+        }
+        assert_eq!(lookup.cached_themes(), vec!["hidpi", "chain-a"]);
+
+        // larger capacity keeps more themes
+        lookup.set_theme_cache_capacity(3);
+        let _ = lookup.theme("symbolic").unwrap();
+        assert_eq!(lookup.cached_themes(), vec!["symbolic", "hidpi", "chain-a"]);
+
+        lookup.purge_themes();
+        assert!(lookup.cached_themes().is_empty());
+    }
+
+    #[test]
+    fn test_independent_lookups() {
+        let themed = IconLookup::new(vec!["tests/icons"]).with_default_theme("themed");
+        let symbolic = IconLookup::new(vec!["tests/icons"]).with_default_theme("symbolic");
+
+        assert_eq!(themed.find_icon("test", 48, 1),
+                    Some("tests/icons/themed/apps/48/test.png".into()));
+        assert_eq!(symbolic.find_icon("test", 48, 1), None);
+        assert_eq!(symbolic.find_icon("go-up", 16, 1),
+                    Some("tests/icons/symbolic/actions/16/go-up.png".into()));
+
+        // invalid default theme fallback to hicolor
+        let invalid = IconLookup::new(vec!["tests/icons"]).with_default_theme("InvalidThemeName");
+        assert_eq!(invalid.find_icon("TestAppIcon", 16, 1),
+                    Some("tests/icons/hicolor/apps/16/TestAppIcon.png".into()));
+
+        let no_fallback = IconLookup::new(vec!["tests/icons"]).with_fallback_theme("InvalidThemeName");
+        assert_eq!(no_fallback.find_icon_with_theme_name("themed", "TestAppIcon", 16, 1), None);
+    }
+
+    #[test]
+    fn test_icon_info() {
+        let lookup = IconLookup::new(vec!["tests/icons"]);
+        let options = IconLookupOptions::default();
+
+        let info = lookup.find_icon_info_with_theme_name("themed", "deepin-deb-installer", 24, 1, &options).unwrap();
+        assert_eq!(info.path(), Path::new("tests/icons/themed/apps/scalable/deepin-deb-installer.svg"));
+        assert_eq!(info.format(), IconFormat::Svg);
+        assert_eq!(info.theme_name(), Some("themed"));
+        assert_eq!(info.directory().map(|x| x.name()), Some("apps/scalable"));
+        assert_eq!(info.directory().map(|x| x.directory_type()), Some(DirectoryType::Scalable(1, 256)));
+        assert!(info.is_exact_size());
+        assert!(!info.is_fallback());
+
+        // closest match
+        let info = lookup.find_icon_info_with_theme_name("themed", "name.with.dot", 48, 1, &options).unwrap();
+        assert_eq!(info.directory().map(|x| (x.size(), x.scale())), Some((16, 1)));
+        assert!(!info.is_exact_size());
+
+        // served by hicolor with name fallback
+        let info = lookup.find_icon_info_with_theme_name("themed", "TestAppIcon-extend", 48, 1, &options).unwrap();
+        assert_eq!(info.path(), Path::new("tests/icons/hicolor/apps/48/TestAppIcon.png"));
+        assert_eq!(info.format(), IconFormat::Png);
+        assert_eq!(info.theme_name(), Some("hicolor"));
+        assert_eq!(info.matched_name(), "TestAppIcon");
+        assert!(info.is_fallback());
+    }
+
+    #[test]
+    fn test_lookup_options() {
+        let lookup = IconLookup::new(vec!["tests/icons"]).with_extra_dirs(vec!["tests/extra-icons"]);
+        let find = |icon: &str, size: i32, options: IconLookupOptions| {
+            lookup.find_icon_info_with_theme_name("themed", icon, size, 1, &options).map(|x| x.into_path())
+        };
+
+        // svg
+        assert_eq!(find("test", 32, IconLookupOptions::new().force_svg(true)),
+                    Some("tests/icons/themed/apps/32/test.png".into()));
+        assert_eq!(find("deepin-deb-installer", 24, IconLookupOptions::new().no_svg(true)),
+                    None);
+        assert_eq!(find("ExtraIcon", 24, IconLookupOptions::new().no_svg(true)),
+                    None);
+
+        // generic fallback and fallback theme
+        assert_eq!(find("deepin-deb-installer-extend", 48, IconLookupOptions::new().no_generic_fallback(true)),
+                    None);
+        assert_eq!(find("TestAppIcon", 16, IconLookupOptions::new().no_fallback_theme(true)),
+                    None);
+        assert_eq!(find("TestAppIcon", 16, IconLookupOptions::new()),
+                    Some("tests/icons/hicolor/apps/16/TestAppIcon.png".into()));
+
+        // extra dirs
+        assert_eq!(find("ExtraIcon", 48, IconLookupOptions::new()),
+                    Some("tests/extra-icons/ExtraIcon.svg".into()));
+        assert_eq!(find("ExtraIcon", 48, IconLookupOptions::new().no_extra_dirs(true)),
+                    None);
+
+        // closest match
+        assert_eq!(find("test", 40, IconLookupOptions::new()),
+                    Some("tests/icons/themed/apps/32/test.png".into()));
+        assert_eq!(find("test", 40, IconLookupOptions::new().prefer_larger(true)),
+                    Some("tests/icons/themed/apps/48/test.png".into()));
+
+        // symbolic
+        let find = |icon: &str, options: IconLookupOptions| {
+            lookup.find_icon_info_with_theme_name("symbolic", icon, 16, 1, &options).map(|x| x.into_path())
+        };
+        assert_eq!(find("edit", IconLookupOptions::new().force_symbolic(true)),
+                    Some("tests/icons/symbolic/actions/16/edit-symbolic.svg".into()));
+        assert_eq!(find("edit-undo-symbolic", IconLookupOptions::new().force_regular(true)),
+                    Some("tests/icons/symbolic/actions/16/edit-undo.png".into()));
+    }
+
+    #[test]
+    fn test_fractional_scale() {
+        assert_eq!(resolve_fractional_scale(1.0), (1, 1.0));
+        assert_eq!(resolve_fractional_scale(1.25), (2, 0.625));
+        assert_eq!(resolve_fractional_scale(1.5), (2, 0.75));
+        assert_eq!(resolve_fractional_scale(2.0000001).0, 2);
+        assert_eq!(resolve_fractional_scale(0.5), (1, 0.5));
+        assert_eq!(resolve_fractional_scale(f64::NAN), (1, 1.0));
+
+        let lookup = IconLookup::new(vec!["tests/icons"]);
+        let options = IconLookupOptions::default();
+
+        let info = lookup.find_icon_info_fractional_with_theme_name("hidpi", "scaled-app", 24, 1.5, &options).unwrap();
+        assert_eq!(info.path(), Path::new("tests/icons/hidpi/24x24@2/apps/scaled-app.png"));
+        assert_eq!(info.residual_scale(), 0.75);
+
+        let info = lookup.find_icon_info_fractional_with_theme_name("hidpi", "scaled-app", 24, 1.0, &options).unwrap();
+        assert_eq!(info.residual_scale(), 1.0);
+        assert_eq!(info.directory().map(|x| x.scale()), Some(1));
+    }
+
+    #[test]
+    fn test_find_best_icon() {
+        let lookup = IconLookup::new(vec!["tests/icons"]).with_extra_dirs(vec!["tests/extra-icons"]);
+        let theme = lookup.theme("themed").unwrap();
+
+        // every name is tried in a theme before the fallback theme
+        assert_eq!(lookup.find_best_icon(&theme, &["TestAppIcon", "test"], 32, 1),
+                    Some("tests/icons/themed/apps/32/test.png".into()));
+
+        // themes before extra dirs
+        assert_eq!(lookup.find_best_icon(&theme, &["ExtraIcon", "TestAppIcon"], 16, 1),
+                    Some("tests/icons/hicolor/apps/16/TestAppIcon.png".into()));
+        assert_eq!(lookup.find_best_icon(&theme, &["no-such-icon", "ExtraIcon"], 16, 1),
+                    Some("tests/extra-icons/ExtraIcon.svg".into()));
+
+        // generic fallback after all names fail
+        assert_eq!(lookup.find_best_icon(&theme, &["test-foo", "TestAppIcon"], 16, 1),
+                    Some("tests/icons/hicolor/apps/16/TestAppIcon.png".into()));
+        let info = lookup.find_best_icon_info(&theme, &["no-such-icon", "test-foo"], 32, 1, &IconLookupOptions::default()).unwrap();
+        assert_eq!(info.path(), Path::new("tests/icons/themed/apps/32/test.png"));
+        assert!(info.is_fallback());
+
+        // stripped names reach extra dirs, like in `find_icon`
+        assert_eq!(lookup.find_best_icon(&theme, &["ExtraIcon-foo"], 16, 1),
+                    Some("tests/extra-icons/ExtraIcon.svg".into()));
+        assert_eq!(lookup.find_best_icon(&theme, &["ExtraIcon-foo"], 16, 1),
+                    lookup.find_icon_in_theme(&theme, "ExtraIcon-foo", 16, 1));
+
+        let empty: &[&str] = &[];
+        assert_eq!(lookup.find_best_icon(&theme, empty, 16, 1), None);
+    }
+
+    #[test]
+    fn test_lookup_with_dir_index() {
+        let lookup = IconLookup::new(vec!["tests/icons", "tests/fake_home/.local/share/icons"]).with_dir_index(true);
+
+        assert_eq!(lookup.find_icon_with_theme_name("themed", "just-in-another-base", 16, 1),
+                    Some("tests/fake_home/.local/share/icons/themed/apps/16/just-in-another-base.png".into()));
+        assert_eq!(lookup.find_icon_with_theme_name("themed", "TestAppIcon", 48, 2),
+                    Some("tests/icons/hicolor/apps/48@2/TestAppIcon.png".into()));
+        assert_eq!(lookup.find_icon_with_theme_name("themed", "deepin-deb-installer-extend", 24, 1),
+                    Some("tests/icons/themed/apps/scalable/deepin-deb-installer.svg".into()));
+
+        lookup.invalidate_dir_index();
+        assert_eq!(lookup.find_icon_with_theme_name("themed", "test", 48, 1),
+                    Some("tests/icons/themed/apps/48/test.png".into()));
+    }
+
+    #[test]
+    fn test_lookup_with_persistent_index() {
+        let cache_dir = Path::new("target/test-lookup-persistent-index");
+        let _ = ::std::fs::remove_dir_all(cache_dir);
+
+        let lookup = IconLookup::new(vec!["tests/icons"]).with_index_cache_dir(cache_dir);
+        assert_eq!(lookup.find_icon_with_theme_name("chain-a", "grandparent-icon", 16, 1),
+                    Some("tests/icons/chain-c/apps/16/grandparent-icon.png".into()));
+        assert_eq!(::std::fs::read_dir(cache_dir).unwrap().count(), 3);
     }
 
     #[test]
@@ -1,39 +1,204 @@
-
 use std::convert::From;
 
+static SYMBOLIC_SUFFIX: &str = "-symbolic";
+
 bitflags! {
-    struct FallbackRules: u32 {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct FallbackRules: u32 {
+        /// Accept `-symbolic` icons.
         const SYMBOLIC = 0b00000001;
+        /// Accept full-color icons.
+        const REGULAR = 0b00000010;
+    }
+}
+
+///
+/// Text direction of the UI an icon is looked up for.
+///
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TextDirection {
+    None,
+    Ltr,
+    Rtl,
+}
+
+impl TextDirection {
+    fn suffix(self) -> Option<&'static str> {
+        match self {
+            TextDirection::None => None,
+            TextDirection::Ltr => Some("-ltr"),
+            TextDirection::Rtl => Some("-rtl"),
+        }
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct IconName {
     inner_name: String,
+    stem: String,
+    origin_stem: String,
+    symbolic: bool,
+    origin_symbolic: bool,
+    rules: FallbackRules,
+    direction: TextDirection,
 }
 
 impl<T> From<T> for IconName
   where T: AsRef<str> {
     fn from(from: T) -> Self {
-        Self {
-            inner_name: from.as_ref().to_string(),
-        }
+        let rules = if from.as_ref().ends_with(SYMBOLIC_SUFFIX) {
+            FallbackRules::SYMBOLIC | FallbackRules::REGULAR
+        } else {
+            FallbackRules::REGULAR
+        };
+
+        Self::with_rules(from, rules)
     }
 }
 
 impl IconName {
+    ///
+    /// Create an icon name with explicit fallback rules.
+    ///
+    /// A symbolic name like `foo-bar-symbolic` with only `FallbackRules::SYMBOLIC`
+    /// never falls back to full-color icons, with only `FallbackRules::REGULAR`
+    /// it's looked up as `foo-bar`.
+    ///
+    pub fn with_rules<T: AsRef<str>>(name: T, rules: FallbackRules) -> Self {
+        let name = name.as_ref();
+        let origin_symbolic = name.ends_with(SYMBOLIC_SUFFIX);
+        let stem = if origin_symbolic { &name[..name.len() - SYMBOLIC_SUFFIX.len()] } else { name };
+
+        // symbolic names try symbolic icons first, regular names try regular icons first.
+        let symbolic = if origin_symbolic {
+            rules.contains(FallbackRules::SYMBOLIC) || !rules.contains(FallbackRules::REGULAR)
+        } else {
+            !rules.contains(FallbackRules::REGULAR) && rules.contains(FallbackRules::SYMBOLIC)
+        };
+
+        let mut r = Self {
+            inner_name: String::new(),
+            stem: stem.to_string(),
+            origin_stem: stem.to_string(),
+            symbolic,
+            origin_symbolic,
+            rules,
+            direction: TextDirection::None,
+        };
+        r.update_name();
+
+        r
+    }
+
+    /// Prefer `name-rtl`/`name-ltr` variants of the icon.
+    pub fn with_direction(mut self, direction: TextDirection) -> Self {
+        self.direction = direction;
+        self
+    }
+
     pub fn name(&self) -> &str {
         &self.inner_name
     }
 
+    pub fn direction(&self) -> TextDirection {
+        self.direction
+    }
+
+    pub fn rules(&self) -> FallbackRules {
+        self.rules
+    }
+
+    pub fn is_symbolic(&self) -> bool {
+        self.symbolic
+    }
+
+    /// Name without the `-symbolic` suffix.
+    pub fn stem(&self) -> &str {
+        &self.stem
+    }
+
+    /// Same name with symbolic icons tried before full-color icons.
+    pub(crate) fn force_symbolic(&self) -> IconName {
+        let name = format!("{}{}", self.origin_stem, SYMBOLIC_SUFFIX);
+
+        IconName::with_rules(name, FallbackRules::SYMBOLIC | FallbackRules::REGULAR).with_direction(self.direction)
+    }
+
+    /// Same name with full-color icons tried before symbolic icons.
+    pub(crate) fn force_regular(&self) -> IconName {
+        IconName::with_rules(&self.origin_stem, FallbackRules::SYMBOLIC | FallbackRules::REGULAR).with_direction(self.direction)
+    }
+
+    ///
+    /// Names to probe for the current fallback step paired with their file names,
+    /// the directional name comes before the plain name.
+    ///
+    /// Like GTK, the direction goes before `-symbolic`, e.g. `go-next-rtl-symbolic`.
+    ///
+    pub(crate) fn lookup_names(&self, exts: &[&str]) -> Vec<(String, Vec<String>)> {
+        let files = |name: &str| -> Vec<String> {
+            let mut files: Vec<String> = exts.iter().map(|ext| format!("{}.{}", name, ext)).collect();
+            if self.symbolic {
+                files.push(format!("{}.symbolic.png", name));
+            }
+
+            files
+        };
+
+        let mut r = vec![];
+
+        if let Some(suffix) = self.direction.suffix() {
+            let name = if self.symbolic {
+                format!("{}{}{}", self.stem, suffix, SYMBOLIC_SUFFIX)
+            } else {
+                format!("{}{}", self.inner_name, suffix)
+            };
+            let files = files(&name);
+
+            r.push((name, files));
+        }
+
+        r.push((self.inner_name.clone(), files(&self.inner_name)));
+
+        r
+    }
+
+    ///
+    /// Strip the last dash-separated segment of the name.
+    ///
+    /// `-symbolic` suffix is kept when stripping, after all symbolic names are
+    /// tried, full-color names are tried if `FallbackRules::REGULAR` is set,
+    /// so `foo-bar-symbolic` falls back to `foo-symbolic`, `foo-bar` then `foo`.
+    /// The text direction is kept, `go-next` with `TextDirection::Rtl` looks up
+    /// `go-rtl` before `go`.
+    ///
     pub fn fallback(&mut self) -> Option<&IconName> {
-        let len = self.inner_name.len();
-        let last_dash = self.inner_name.rfind('-')?;
+        if let Some(last_dash) = self.stem.rfind('-') {
+            self.stem.truncate(last_dash);
+        } else {
+            // switch to the other phase
+            if self.symbolic != self.origin_symbolic { return None; }
 
-        let _ = self.inner_name.drain(last_dash..len).count();
+            let other = if self.symbolic { FallbackRules::REGULAR } else { FallbackRules::SYMBOLIC };
+            if !self.rules.contains(other) { return None; }
+
+            self.symbolic = !self.symbolic;
+            self.stem = self.origin_stem.clone();
+        }
+
+        self.update_name();
 
         Some(self)
     }
+
+    fn update_name(&mut self) {
+        self.inner_name = if self.symbolic {
+            format!("{}{}", self.stem, SYMBOLIC_SUFFIX)
+        } else {
+            self.stem.clone()
+        };
+    }
 }
 
 #[cfg(test)]
@@ -53,4 +218,45 @@ mod test {
 
         assert!(icon_name.fallback().is_none());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_symbolic_fallback() {
+        let mut icon_name = IconName::from("foo-bar-symbolic");
+        assert_eq!(icon_name.name(), "foo-bar-symbolic");
+        assert_eq!(icon_name.stem(), "foo-bar");
+
+        let names: Vec<String> = (0..).map_while(|_| icon_name.fallback().map(|x| x.name().to_string())).collect();
+        assert_eq!(names, vec!["foo-symbolic", "foo-bar", "foo"]);
+
+        // require symbolic icons
+        let mut icon_name = IconName::with_rules("foo-bar-symbolic", FallbackRules::SYMBOLIC);
+        let names: Vec<String> = (0..).map_while(|_| icon_name.fallback().map(|x| x.name().to_string())).collect();
+        assert_eq!(names, vec!["foo-symbolic"]);
+
+        // only full-color icons
+        let mut icon_name = IconName::with_rules("foo-bar-symbolic", FallbackRules::REGULAR);
+        assert_eq!(icon_name.name(), "foo-bar");
+        assert!(!icon_name.is_symbolic());
+        let names: Vec<String> = (0..).map_while(|_| icon_name.fallback().map(|x| x.name().to_string())).collect();
+        assert_eq!(names, vec!["foo"]);
+    }
+
+    #[test]
+    fn test_directional_names() {
+        let mut icon_name = IconName::from("go-next").with_direction(TextDirection::Rtl);
+        let names: Vec<String> = icon_name.lookup_names(&["png"]).into_iter().map(|x| x.0).collect();
+        assert_eq!(names, vec!["go-next-rtl", "go-next"]);
+
+        icon_name.fallback();
+        let names: Vec<(String, Vec<String>)> = icon_name.lookup_names(&["png"]);
+        assert_eq!(names, vec![("go-rtl".to_string(), vec!["go-rtl.png".to_string()]),
+                               ("go".to_string(), vec!["go.png".to_string()])]);
+
+        let icon_name = IconName::from("go-next-symbolic").with_direction(TextDirection::Ltr);
+        let names: Vec<(String, Vec<String>)> = icon_name.lookup_names(&["svg"]);
+        assert_eq!(names, vec![("go-next-ltr-symbolic".to_string(), vec!["go-next-ltr-symbolic.svg".to_string(),
+                                                                         "go-next-ltr-symbolic.symbolic.png".to_string()]),
+                               ("go-next-symbolic".to_string(), vec!["go-next-symbolic.svg".to_string(),
+                                                                     "go-next-symbolic.symbolic.png".to_string()])]);
+    }
+}
@@ -1,84 +1,168 @@
 
 use icon_name::IconName;
-
-use ini::Ini;
+use icon_info::IconInfo;
+use lookup_options::IconLookupOptions;
+use icon_lookup;
+use error::IconThemeError;
+use dir_index::DirIndex;
+use gtk_cache::GtkIconCacheReader;
+
+use ini::{Ini, Properties};
 use rayon::prelude::*;
-use gtk_icon_cache::GtkIconCache;
-use lru_cache::LruCache;
 
 use std::path::{Path, PathBuf};
-use std::env;
+use std::collections::HashMap;
+use std::fs;
+#[cfg(test)]
 use std::sync::Mutex;
 use std::sync::Arc;
 
-static BASIC_EXTS: &'static [&'static str] = &["png", "svg"];
-static EXTRA_EXTS: &'static [&'static str] = &["png", "svg", "xpm"];
-
-lazy_static! {
-    static ref USER_ICON_DIR: Vec<PathBuf> = get_user_icon_dir();
-    static ref ICON_THEME_CACHE: Mutex<LruCache<String, Arc<IconTheme>>> = Mutex::new(LruCache::new(8));
-}
+static EXTRA_EXTS: &[&str] = &["png", "svg", "xpm"];
 
 #[cfg(test)]
 lazy_static! {
     pub static ref TEST_ENV_MUTEX: Mutex<()> = Mutex::new(());
 }
 
-fn get_user_icon_dir() -> Vec<PathBuf> {
-
-    if let Ok(dirs) = env::var("XDG_DATA_DIRS") {
-        return dirs.split(':')
-                   .map(|x| Into::<PathBuf>::into(x).join("icons"))
-                   .filter(|x| x.is_dir())
-                   .collect()
-    }
-
-    if let Ok(dir) = env::var("XDG_DATA_HOME") {
-        let dir: PathBuf = format!("{}/icons", dir).into();
-        if dir.is_dir() {
-            return vec![dir];
+/// Writable copies of `tests/scratch-theme`, for tests changing theme files.
+#[cfg(test)]
+pub(crate) mod scratch_theme {
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    /// Copy the fixture to `dir`, emptied first, with `icons` installed.
+    pub(crate) fn create(dir: &Path, icons: &[&str]) {
+        let _ = fs::remove_dir_all(dir);
+        fs::create_dir_all(dir.join("apps/16")).unwrap();
+        fs::copy("tests/scratch-theme/index.theme", dir.join("index.theme")).unwrap();
+
+        for icon in icons {
+            install(dir, icon);
         }
     }
 
-    if let Ok(dir) = env::var("HOME") {
-        let dir: PathBuf = format!("{}/.local/share/icons", dir).into();
-        if dir.is_dir() {
-            return vec![dir];
-        }
+    /// Install `apps/16/<icon>.png`, returns its path.
+    pub(crate) fn install(dir: &Path, icon: &str) -> PathBuf {
+        let path = dir.join("apps/16").join(format!("{}.png", icon));
+        fs::copy("tests/scratch-theme/icon.png", &path).unwrap();
+
+        path
     }
 
-    vec![]
+    pub(crate) fn set_mtime(path: &Path, secs: u64) {
+        fs::File::open(path).unwrap().set_modified(UNIX_EPOCH + Duration::from_secs(secs)).unwrap();
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct IconTheme {
-    name: String,
-    inherits: Vec<String>,
+    internal_name: String,
+    header: ThemeHeader,
     extra_dirs: Vec<PathBuf>,
     base_dirs: Vec<PathBuf>,
     sub_dirs: Vec<IconDirectory>,
-    gtk_cache: Option<GtkIconCache>,
+    /// `icon-theme.cache` of each base dir
+    gtk_caches: Vec<Option<Arc<GtkIconCacheReader>>>,
+    dir_index: Option<DirIndex>,
 }
 
 #[derive(Debug, Clone)]
 pub struct IconDirectory {
     name: String,
+    context: Option<IconContext>,
     type_: DirectoryType,
     size: i32,
     scale: i32,
 }
 
-#[derive(Debug, Clone)]
-enum DirectoryType {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DirectoryType {
     Fixed,
+    /// `MinSize` and `MaxSize`
     Scalable(i32, i32),
+    /// `Threshold`
     Threshold(i32),
 }
 
+///
+/// UI areas of KDE's `<Group>Default` and `<Group>Sizes` keys, e.g. `PanelDefault`.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IconGroup {
+    Desktop,
+    Toolbar,
+    MainToolbar,
+    Small,
+    Panel,
+    Dialog,
+}
+
+impl IconGroup {
+    fn all() -> &'static [IconGroup] {
+        &[IconGroup::Desktop, IconGroup::Toolbar, IconGroup::MainToolbar,
+          IconGroup::Small, IconGroup::Panel, IconGroup::Dialog]
+    }
+
+    fn key_prefix(self) -> &'static str {
+        match self {
+            IconGroup::Desktop => "Desktop",
+            IconGroup::Toolbar => "Toolbar",
+            IconGroup::MainToolbar => "MainToolbar",
+            IconGroup::Small => "Small",
+            IconGroup::Panel => "Panel",
+            IconGroup::Dialog => "Dialog",
+        }
+    }
+}
+
+///
+/// The `Context` key of an icon directory.
+///
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum IconContext {
+    Actions,
+    Animations,
+    Applications,
+    Categories,
+    Devices,
+    Emblems,
+    Emotes,
+    FileSystems,
+    International,
+    MimeTypes,
+    Places,
+    Status,
+    Stock,
+    Other(String),
+}
+
+impl<'a> From<&'a str> for IconContext {
+    fn from(from: &'a str) -> Self {
+        match from {
+            "Actions" => IconContext::Actions,
+            "Animations" => IconContext::Animations,
+            "Applications" => IconContext::Applications,
+            "Categories" => IconContext::Categories,
+            "Devices" => IconContext::Devices,
+            "Emblems" => IconContext::Emblems,
+            "Emotes" => IconContext::Emotes,
+            "FileSystems" => IconContext::FileSystems,
+            "International" => IconContext::International,
+            "MimeTypes" => IconContext::MimeTypes,
+            "Places" => IconContext::Places,
+            "Status" => IconContext::Status,
+            "Stock" => IconContext::Stock,
+            other => IconContext::Other(other.to_string()),
+        }
+    }
+}
+
 impl Default for IconDirectory {
     fn default() -> Self {
         Self {
             name: String::new(),
+            context: None,
             type_: DirectoryType::Threshold(2),
             size: 0,
             scale: 1,
@@ -92,12 +176,13 @@ impl Default for IconTheme {
         let extra_dirs = if cfg!(test) { vec![] } else { vec!["/usr/share/pixmaps".into()] };
 
         Self {
-            name: String::new(),
-            inherits: vec![],
+            internal_name: String::new(),
+            header: ThemeHeader::default(),
             extra_dirs,
             base_dirs: vec![],
             sub_dirs: vec![],
-            gtk_cache: None,
+            gtk_caches: vec![],
+            dir_index: None,
         }
     }
 }
@@ -123,7 +208,9 @@ impl IconDirectory {
             r.scale = scale;
         }
 
-        match properties.get("Type").map(|x| x.as_str()) {
+        r.context = properties.get("Context").map(|x| x.trim().into());
+
+        match properties.get("Type") {
             Some("Fixed") => {
                 r.type_ = DirectoryType::Fixed;
             },
@@ -138,7 +225,7 @@ impl IconDirectory {
 
                 r.type_ = DirectoryType::Threshold(threshold.unwrap_or(2));
             },
-            unknown @ _ => {
+            unknown => {
                 error!("Directory Type is invalid: {:?}", unknown);
             },
         }
@@ -146,144 +233,280 @@ impl IconDirectory {
         r
     }
 
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn context(&self) -> Option<&IconContext> {
+        self.context.as_ref()
+    }
+
+    pub fn directory_type(&self) -> DirectoryType {
+        self.type_
+    }
+
+    /// Nominal size
+    pub fn size(&self) -> i32 {
+        self.size
+    }
+
+    pub fn scale(&self) -> i32 {
+        self.scale
+    }
+
     /// DirectoryMatchesSize
     pub fn matches_size(&self, size: i32, scale: i32) -> bool {
         if scale != self.scale {
             return false;
         }
 
-        return match self.type_ {
+        match self.type_ {
             DirectoryType::Fixed => self.size == size,
             DirectoryType::Scalable(min, max) => min <= size && max >= size,
             DirectoryType::Threshold(threshold) => (self.size - size).abs() <= threshold,
-        };
+        }
     }
 
-    /// DirectorySizeDistance
+    /// DirectorySizeDistance, compared in effective pixels so directories of other scales can be ranked.
     pub fn size_distance(&self, size: i32, scale: i32) -> i32 {
-        match self.type_ {
-            DirectoryType::Fixed => {
-                (self.size * self.scale - size * scale).abs()
-            },
-            DirectoryType::Scalable(min, max) => {
-                if size * scale < min * scale {
-                    min * scale - size * scale
-                } else {
-                    size * scale - max * scale
-                }
-            },
-            DirectoryType::Threshold(_threshold) => {
-                // FIXME
-                (self.size * self.scale - size * scale).abs()
-            },
+        let pixels = size * scale;
+
+        let (min, max) = match self.type_ {
+            DirectoryType::Fixed => return (self.size * self.scale - pixels).abs(),
+            DirectoryType::Scalable(min, max) => (min, max),
+            DirectoryType::Threshold(threshold) => (self.size - threshold, self.size + threshold),
+        };
+
+        if pixels < min * self.scale {
+            min * self.scale - pixels
+        } else if pixels > max * self.scale {
+            pixels - max * self.scale
+        } else {
+            0
         }
     }
 }
 
-fn search_gtk_cache_in_dir(dir: &Path) -> Option<GtkIconCache> {
+///
+/// Load `icon-theme.cache` of a theme dir, a cache older than the theme dir or
+/// any of `sub_dirs` misses icons installed later and is ignored like GTK does.
+///
+fn search_gtk_cache_in_dir(dir: &Path, sub_dirs: &[IconDirectory]) -> Option<Arc<GtkIconCacheReader>> {
 
     let f = dir.join("icon-theme").with_extension("cache");
-    if !f.is_file() { return None; }
+    let cache_mtime = fs::metadata(&f).and_then(|x| x.modified()).ok()?;
+
+    let dirs = Some(dir.to_path_buf()).into_iter().chain(sub_dirs.iter().map(|x| dir.join(&x.name)));
+    for d in dirs {
+        if let Ok(mtime) = fs::metadata(&d).and_then(|x| x.modified()) {
+            if mtime > cache_mtime {
+                warn!("{} is older than {}, ignore it", f.display(), d.display());
+                return None;
+            }
+        }
+    }
+
+    match GtkIconCacheReader::open(&f, sub_dirs) {
+        Ok(cache) => Some(Arc::new(cache)),
+        Err(e) => {
+            warn!("failed to load {}: {}", f.display(), e);
+            None
+        },
+    }
+}
+
+///
+/// Value of `key[locale]` best matching `locale`, like the desktop entry spec.
+///
+/// `lang_COUNTRY.ENCODING@MODIFIER` tries `lang_COUNTRY@MODIFIER`, `lang_COUNTRY`,
+/// `lang@MODIFIER` then `lang`, the encoding is ignored.
+///
+fn match_locale<'a>(values: &'a [(String, String)], locale: &str) -> Option<&'a str> {
+    let (locale, modifier) = match locale.find('@') {
+        Some(i) => (&locale[..i], Some(&locale[i..])),
+        None => (locale, None),
+    };
+    let locale = locale.split('.').next().unwrap_or("");
+    let lang = locale.split('_').next().unwrap_or("");
+    if lang.is_empty() { return None; }
+
+    let mut candidates = vec![];
+    if let Some(modifier) = modifier {
+        candidates.push(format!("{}{}", locale, modifier));
+    }
+    candidates.push(locale.to_string());
+    if let Some(modifier) = modifier {
+        candidates.push(format!("{}{}", lang, modifier));
+    }
+    candidates.push(lang.to_string());
+
+    candidates.iter()
+              .filter_map(|c| values.iter().find(|x| x.0 == *c))
+              .map(|x| x.1.as_str())
+              .next()
+}
 
-    GtkIconCache::with_file_path(f).ok()
+/// Comma separated values of `index.theme`, empty items are dropped.
+fn split_list(value: &str) -> Vec<String> {
+    value.split(',')
+         .map(|x| x.trim())
+         .filter(|x| !x.is_empty())
+         .map(|x| x.to_string())
+         .collect()
+}
+
+///
+/// Keys of the `[Icon Theme]` group of `index.theme` describing the theme,
+/// shared by `IconTheme` and `IconThemeInfo`.
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct ThemeHeader {
+    pub(crate) name: String,
+    pub(crate) comment: String,
+    /// (locale, value) of `Name[xx]`
+    localized_names: Vec<(String, String)>,
+    /// (locale, value) of `Comment[xx]`
+    localized_comments: Vec<(String, String)>,
+    pub(crate) hidden: bool,
+    pub(crate) example: Option<String>,
+    pub(crate) display_depth: Option<i32>,
+    group_sizes: HashMap<IconGroup, (Option<i32>, Vec<i32>)>,
+    pub(crate) inherits: Vec<String>,
+}
+
+impl ThemeHeader {
+    pub(crate) fn from_properties(properties: &Properties) -> Self {
+        let mut r = Self {
+            name: properties.get("Name").unwrap_or("").to_string(),
+            comment: properties.get("Comment").unwrap_or("").to_string(),
+            inherits: split_list(properties.get("Inherits").unwrap_or("")),
+            hidden: properties.get("Hidden").map(|x| x.trim() == "true").unwrap_or(false),
+            example: properties.get("Example").map(|x| x.trim().to_string()).filter(|x| !x.is_empty()),
+            display_depth: properties.get("DisplayDepth").and_then(|x| x.trim().parse().ok()),
+            ..Default::default()
+        };
+
+        for (key, value) in properties.iter() {
+            let (key, locale) = match (key.find('['), key.ends_with(']')) {
+                (Some(i), true) => (&key[..i], &key[i + 1..key.len() - 1]),
+                _ => continue,
+            };
+
+            match key {
+                "Name" => r.localized_names.push((locale.to_string(), value.to_string())),
+                "Comment" => r.localized_comments.push((locale.to_string(), value.to_string())),
+                _ => {},
+            }
+        }
+
+        for group in IconGroup::all() {
+            let default = properties.get(&format!("{}Default", group.key_prefix()) as &str)
+                                    .and_then(|x| x.trim().parse().ok());
+            let sizes: Vec<i32> = properties.get(&format!("{}Sizes", group.key_prefix()) as &str)
+                                            .map(split_list)
+                                            .unwrap_or_default()
+                                            .iter()
+                                            .filter_map(|x| x.parse().ok())
+                                            .collect();
+
+            if default.is_some() || !sizes.is_empty() {
+                r.group_sizes.insert(*group, (default, sizes));
+            }
+        }
+
+        r
+    }
+
+    pub(crate) fn localized_name(&self, locale: &str) -> &str {
+        match_locale(&self.localized_names, locale).unwrap_or(&self.name)
+    }
+
+    pub(crate) fn localized_comment(&self, locale: &str) -> &str {
+        match_locale(&self.localized_comments, locale).unwrap_or(&self.comment)
+    }
+
+    pub(crate) fn default_size(&self, group: IconGroup) -> Option<i32> {
+        self.group_sizes.get(&group).and_then(|x| x.0)
+    }
+
+    pub(crate) fn sizes(&self, group: IconGroup) -> &[i32] {
+        self.group_sizes.get(&group).map(|x| &x.1[..]).unwrap_or(&[])
+    }
 }
 
 impl IconTheme {
-    pub fn from_dir<T: AsRef<Path>>(path: T) -> Result<IconTheme, ()> {
-        let f = Ini::load_from_file(path.as_ref().join("index").with_extension("theme")).map_err(|_| ())?;
+    pub fn from_dir<T: AsRef<Path>>(path: T) -> Result<IconTheme, IconThemeError> {
+        let index = path.as_ref().join("index").with_extension("theme");
+        let f = Ini::load_from_file(&index).map_err(|e| IconThemeError::from_ini(index, e))?;
 
         let mut r = Self {
+            internal_name: path.as_ref().file_name().map(|x| x.to_string_lossy().to_string()).unwrap_or_default(),
             base_dirs: vec![path.as_ref().into()],
-            gtk_cache: search_gtk_cache_in_dir(path.as_ref()),
             ..Default::default()
         };
         let mut directories = vec![];
 
         if let Some(properties) = f.section(Some("Icon Theme")) {
-            r.name = properties.get("Name").unwrap_or(&String::new()).to_string();
+            r.header = ThemeHeader::from_properties(properties);
 
-            if let Some(list) = properties.get("Inherits").map(|x| x.split(',')) {
-                let inherits: Vec<String> = list.map(|x| x.to_string()).collect();
-                r.inherits = inherits;
-            }
+            // `ScaledDirectories` hides HiDPI directories from parsers unaware of `Scale`
+            for key in &["Directories", "ScaledDirectories"] {
+                let list = properties.get(*key).map(|x| x.split(',')).into_iter().flatten();
 
-            if let Some(list) = properties.get("Directories").map(|x| x.split(',')) {
-                directories = list.map(|x| x.to_string()).collect();
+                for dir in list.map(|x| x.trim()).filter(|x| !x.is_empty()) {
+                    if !directories.iter().any(|x| x == dir) {
+                        directories.push(dir.to_string());
+                    }
+                }
             }
         };
 
         r.sub_dirs = directories.iter().map(
             |x| IconDirectory::with_settings(&f, x)
         ).collect();
+        r.gtk_caches = vec![search_gtk_cache_in_dir(path.as_ref(), &r.sub_dirs)];
 
         Ok(r)
     }
 
-    pub fn from_name<T: AsRef<str>>(name: T) -> Result<Arc<IconTheme>, ()> {
-
-        let name = name.as_ref();
-        let mut cache = ICON_THEME_CACHE.lock().unwrap();
-
-        if !cache.contains_key(name) {
-            let theme = Self::from_name_interal(name)?;
-            let _ = cache.insert(name.to_string(), Arc::new(theme));
-        }
-
-        Ok(cache.get_mut(name).unwrap().clone())
+    /// Load a theme by name through the default `IconLookup`.
+    pub fn from_name<T: AsRef<str>>(name: T) -> Result<Arc<IconTheme>, IconThemeError> {
+        icon_lookup::default_lookup().theme(name)
     }
 
-    fn from_name_interal<T: AsRef<str>>(name: T) -> Result<IconTheme, ()> {
+    /// Load a theme by name, merging its directories found in every base dir.
+    pub(crate) fn from_name_in_dirs<T: AsRef<str>>(name: T, base_dirs: &[PathBuf], extra_dirs: &[PathBuf]) -> Result<IconTheme, IconThemeError> {
 
-        let system_dir: PathBuf = if cfg!(test) {
-            format!("tests/icons/{}", name.as_ref()).into()
-        } else {
-            format!("/usr/share/icons/{}", name.as_ref()).into()
-        };
+        let name = name.as_ref();
+        let theme_dirs: Vec<PathBuf> = base_dirs.iter()
+                                                .map(|x| x.join(name))
+                                                .filter(|x| x.is_dir())
+                                                .collect();
 
-        let user_dirs: Vec<PathBuf> =
-            if cfg!(test) {
-                  get_user_icon_dir()
-                    .iter()
-                    .map(|x| format!("{}/{}", x.display(), name.as_ref()).into())
-                    .filter(|x: &PathBuf| x.is_dir())
-                    .collect()
-            } else {
-                  USER_ICON_DIR
-                    .iter()
-                    .map(|x| format!("{}/{}", x.display(), name.as_ref()).into())
-                    .filter(|x: &PathBuf| x.is_dir())
-                    .filter(|x| x.is_dir())
-                    .collect()
-            };
+        if theme_dirs.is_empty() {
+            return Err(IconThemeError::ThemeNotFound(name.to_string()));
+        }
 
         // construct new theme object
-        let mut theme = if user_dirs.is_empty() {
-            Self::from_dir(&system_dir)?
-        } else {
-            if let Some(dir) = user_dirs
-                                .iter()
-                                .filter(|x| x.join("index").with_extension("theme").is_file())
-                                .next() {
-                Self::from_dir(dir)?
-            } else {
-                return Err(());
-            }
+        let mut theme = match theme_dirs.iter().find(|x| x.join("index").with_extension("theme").is_file()) {
+            Some(dir) => Self::from_dir(dir)?,
+            None => return Err(IconThemeError::IndexNotFound(theme_dirs[0].join("index").with_extension("theme"))),
         };
 
-        // append user-side dirs
-        for dir in user_dirs.iter().filter(|x| x.is_dir()) {
+        theme.internal_name = name.to_string();
+        theme.extra_dirs = extra_dirs.to_vec();
+
+        // append all other dirs
+        for dir in theme_dirs.iter() {
             theme.append_base_dir(dir);
         }
 
-        // append system-side dir
-        theme.append_base_dir(&system_dir);
-
         Ok(theme)
     }
 
     // #[cfg(test)]
     // fn clear_gtk_cache(&mut self) {
-    //     self.gtk_cache = None;
+    //     self.gtk_caches.iter_mut().for_each(|x| *x = None);
     // }
 
     fn append_base_dir<T: AsRef<Path>>(&mut self, path: T) {
@@ -291,6 +514,7 @@ impl IconTheme {
         let p = path.as_ref().into();
 
         if !self.base_dirs.contains(&p) {
+            self.gtk_caches.push(search_gtk_cache_in_dir(&p, &self.sub_dirs));
             self.base_dirs.push(p);
         }
     }
@@ -301,52 +525,281 @@ impl IconTheme {
         self.extra_dirs.push(path.as_ref().into());
     }
 
-    fn sub_dirs_for_icon<T: AsRef<str>>(&self, icon: T) -> Vec<&IconDirectory> {
-        match self.gtk_cache.as_ref().and_then(|x| x.lookup(icon.as_ref())) {
-            Some(dirs) => {
-                self.sub_dirs.par_iter()
-                    .filter(|x| dirs.contains(&&x.name))
-                    .collect()
-            }
-            _ => self.sub_dirs.iter().collect(),
+    ///
+    /// List icon files of each base dir once on first lookup instead of testing
+    /// every candidate file, the listing is kept until `invalidate_dir_index`.
+    ///
+    pub fn with_dir_index(mut self, enable: bool) -> Self {
+        self.dir_index = if enable { Some(DirIndex::default()) } else { None };
+        self
+    }
+
+    ///
+    /// Like `with_dir_index`, but the listing is also saved in `cache_dir` and reused
+    /// by later processes until the mtime of any theme directory changes.
+    ///
+    pub fn with_persistent_index<T: Into<PathBuf>>(mut self, cache_dir: T) -> Self {
+        self.dir_index = Some(DirIndex::with_cache_dir(cache_dir.into()));
+        self
+    }
+
+    /// Every base dir has a valid `icon-theme.cache`.
+    pub(crate) fn has_gtk_cache(&self) -> bool {
+        self.gtk_caches.iter().all(|x| x.is_some())
+    }
+
+    /// Drop the listing of `with_dir_index`, e.g. after icons are installed.
+    pub fn invalidate_dir_index(&self) {
+        if let Some(ref index) = self.dir_index {
+            index.invalidate();
+        }
+    }
+
+    ///
+    /// Theme directories of each file name in the GTK cache of each base dir,
+    /// `None` for base dirs without a cache.
+    ///
+    fn cached_files(&self, file_names: &[String]) -> Vec<Option<Vec<Vec<usize>>>> {
+        self.gtk_caches.iter()
+            .map(|cache| cache.as_ref().map(|x| file_names.iter().map(|f| x.dirs_of_file(f)).collect()))
+            .collect()
+    }
+
+    fn sub_dirs_for_icon(&self, cached: &[Option<Vec<Vec<usize>>>]) -> Vec<(usize, &IconDirectory)> {
+        if cached.iter().any(|x| x.is_none()) {
+            return self.sub_dirs.iter().enumerate().collect();
         }
+
+        let mut dirs: Vec<usize> = cached.iter().flatten().flatten().flatten().cloned().collect();
+        dirs.sort_unstable();
+        dirs.dedup();
+
+        dirs.into_iter().map(|x| (x, &self.sub_dirs[x])).collect()
+    }
+
+    ///
+    /// Path of `base_dirs[base]/sub_dirs[sub]/file_names[file]` if the file exists.
+    ///
+    /// Files recorded in the GTK cache are trusted without `stat` unless
+    /// `verify_files` is set, otherwise the in-memory index or the filesystem is asked.
+    ///
+    fn file_exists(&self, cached: &[Option<Vec<Vec<usize>>>], base: usize, sub: usize, file_names: &[String], file: usize, options: &IconLookupOptions) -> Option<PathBuf> {
+        let base_dir = &self.base_dirs[base];
+        let file_name = &file_names[file];
+        let path = || -> PathBuf { format!("{}/{}/{}", base_dir.display(), self.sub_dirs[sub].name, file_name).into() };
+
+        let exists = match cached[base] {
+            Some(ref files) => {
+                if !files[file].contains(&sub) { return None; }
+
+                let p = path();
+                return if !options.is_verify_files() || p.is_file() { Some(p) } else { None };
+            },
+            None => match self.dir_index {
+                Some(ref index) => index.contains(base_dir, &self.sub_dirs, sub, file_name),
+                None => base_dir.join(&self.sub_dirs[sub].name).join(file_name).is_file(),
+            },
+        };
+
+        if exists { Some(path()) } else { None }
+    }
+
+    pub fn internal_name(&self) -> &str {
+        &self.internal_name
     }
 
     pub fn parents(&self) -> &Vec<String> {
-        &self.inherits
+        &self.header.inherits
+    }
+
+    /// `Name`, the name to show to users.
+    pub fn name(&self) -> &str {
+        &self.header.name
+    }
+
+    /// `Name[xx]` best matching `locale` like `de_DE.UTF-8`, `Name` if none matches.
+    pub fn localized_name(&self, locale: &str) -> &str {
+        self.header.localized_name(locale)
+    }
+
+    pub fn comment(&self) -> &str {
+        &self.header.comment
+    }
+
+    /// `Comment[xx]` best matching `locale`, `Comment` if none matches.
+    pub fn localized_comment(&self, locale: &str) -> &str {
+        self.header.localized_comment(locale)
+    }
+
+    /// `Hidden`, the theme should not be shown in theme choosers.
+    pub fn is_hidden(&self) -> bool {
+        self.header.hidden
+    }
+
+    /// `Example`, name of an icon representing the theme.
+    pub fn example(&self) -> Option<&str> {
+        self.header.example.as_deref()
+    }
+
+    /// KDE's `DisplayDepth`, the color depth icons are designed for.
+    pub fn display_depth(&self) -> Option<i32> {
+        self.header.display_depth
+    }
+
+    /// KDE's `<Group>Default`, e.g. `PanelDefault`.
+    pub fn default_size(&self, group: IconGroup) -> Option<i32> {
+        self.header.default_size(group)
+    }
+
+    /// KDE's `<Group>Sizes`, sizes offered for the UI area, e.g. `ToolbarSizes`.
+    pub fn sizes(&self, group: IconGroup) -> &[i32] {
+        self.header.sizes(group)
+    }
+
+    /// Directories the theme is found in, the first one contains `index.theme`.
+    pub fn base_dirs(&self) -> &[PathBuf] {
+        &self.base_dirs
+    }
+
+    /// Sub directories listed in `index.theme`.
+    pub fn directories(&self) -> &[IconDirectory] {
+        &self.sub_dirs
+    }
+
+    ///
+    /// All themes this theme inherits from, resolved depth-first like the
+    /// spec's FindIconHelper. Cycles and duplicated parents are skipped.
+    ///
+    /// Parents are loaded by `icon_lookup::default_lookup()` from the global
+    /// search path, use `IconLookup::inheritance_chain` for themes loaded by
+    /// another lookup context or by `from_dir`.
+    ///
+    pub fn inheritance_chain(&self) -> Vec<Arc<IconTheme>> {
+        icon_lookup::default_lookup().inheritance_chain(self)
+    }
+
+    pub(crate) fn inheritance_chain_with<F>(&self, load: &F) -> Vec<Arc<IconTheme>>
+      where F: Fn(&str) -> Result<Arc<IconTheme>, IconThemeError> {
+
+        let mut visited = vec![self.internal_name.clone()];
+        let mut chain = vec![];
+
+        self.find_in_parents(load, &mut visited, &mut |theme| -> Option<()> {
+            chain.push(theme.clone());
+            None
+        });
+
+        chain
+    }
+
+    ///
+    /// Walk the inheritance chain depth-first until `find` returns something.
+    ///
+    /// Parents are loaded only when the walk reaches them, names of parents
+    /// walked are added to `visited`, which should contain this theme.
+    ///
+    pub(crate) fn find_in_parents<F, G, R>(&self, load: &F, visited: &mut Vec<String>, find: &mut G) -> Option<R>
+      where F: Fn(&str) -> Result<Arc<IconTheme>, IconThemeError>,
+            G: FnMut(&Arc<IconTheme>) -> Option<R> {
+        for parent in &self.header.inherits {
+            if visited.contains(parent) {
+                debug!("skip visited parent theme {} of {}", parent, self.internal_name);
+                continue;
+            }
+            visited.push(parent.clone());
+
+            match load(parent) {
+                Ok(theme) => {
+                    if let Some(r) = find(&theme).or_else(|| theme.find_in_parents(load, visited, find)) {
+                        return Some(r);
+                    }
+                },
+                Err(e) => warn!("failed to load parent theme of {}: {}", self.internal_name, e),
+            }
+        }
+
+        None
     }
 
     pub fn lookup_icon(&self, name: &IconName, size: i32, scale: i32) -> Option<PathBuf> {
+        self.lookup_icon_info(name, size, scale, &IconLookupOptions::default()).map(|x| x.into_path())
+    }
 
-        let ref name = name.name();
-        let ref sub_dirs = self.sub_dirs_for_icon(name);
+    /// Like `lookup_icon`, but tell where the icon comes from.
+    pub fn lookup_icon_info(&self, name: &IconName, size: i32, scale: i32, options: &IconLookupOptions) -> Option<IconInfo> {
+        self.lookup_icon_internal(&options.apply(name), None, size, scale, options)
+    }
 
-        for sub in sub_dirs.iter() {
-            if !sub.matches_size(size, scale) { continue; }
+    /// Like `lookup_icon`, but only search directories of the given `Context`.
+    pub fn lookup_icon_in_context(&self, name: &IconName, context: &IconContext, size: i32, scale: i32) -> Option<PathBuf> {
+        self.lookup_icon_internal(name, Some(context), size, scale, &IconLookupOptions::default()).map(|x| x.into_path())
+    }
+
+    fn lookup_icon_internal(&self, name: &IconName, context: Option<&IconContext>, size: i32, scale: i32, options: &IconLookupOptions) -> Option<IconInfo> {
+
+        for (name, file_names) in name.lookup_names(options.exts()) {
+            if let Some((p, sub, exact)) = self.lookup_file(&file_names, context, size, scale, options) {
+                return Some(IconInfo::new(p, name).with_directory(&self.internal_name, sub, exact));
+            }
+        }
+
+        // extra dirs don't have any context
+        if context.is_some() { return None; }
 
-            for base in self.base_dirs.iter() {
-                for ext in BASIC_EXTS.iter() {
-                    let p: PathBuf = format!("{}/{}/{}.{}", base.display(), sub.name, name, ext).into();
+        self.lookup_extra_icon_info(name, options)
+    }
+
+    /// Search only extra dirs like `/usr/share/pixmaps`, `name` has `options` applied.
+    pub(crate) fn lookup_extra_icon_info(&self, name: &IconName, options: &IconLookupOptions) -> Option<IconInfo> {
+
+        if options.is_no_extra_dirs() { return None; }
+
+        let lookup_names = name.lookup_names(options.extra_exts());
+        let file_names: Vec<(&String, &String)> = lookup_names.iter()
+                                                              .flat_map(|x| x.1.iter().map(move |f| (&x.0, f)))
+                                                              .collect();
+        let extra = self.extra_dirs.par_iter()
+                        .filter(|x| x.is_dir())
+                        .flat_map(|x| file_names.par_iter()
+                                        .map_with(x, |x, &(name, file_name)| (name, x.join(file_name))))
+                        .find_any(|x| x.1.is_file());
+
+        extra.map(|(name, p)| IconInfo::new(p, name))
+    }
 
-                    if p.is_file() { return Some(p); }
+    fn lookup_file(&self, file_names: &[String], context: Option<&IconContext>, size: i32, scale: i32, options: &IconLookupOptions) -> Option<(PathBuf, &IconDirectory, bool)> {
+
+        let cached = self.cached_files(file_names);
+        let mut sub_dirs = self.sub_dirs_for_icon(&cached);
+
+        if let Some(context) = context {
+            sub_dirs.retain(|x| x.1.context.as_ref() == Some(context));
+        }
+
+        for &(i, sub) in sub_dirs.iter() {
+            if !sub.matches_size(size, scale) { continue; }
+
+            for base in 0..self.base_dirs.len() {
+                for file in 0..file_names.len() {
+                    if let Some(p) = self.file_exists(&cached, base, i, file_names, file, options) { return Some((p, sub, true)); }
                 }
             }
         }
 
-        // test closest file
-        let mut minimal_distance = i32::max_value();
-        let mut closest_file: Option<PathBuf> = None;
+        // test closest file in directories of any scale, smaller directories are ranked after
+        // larger ones if `prefer_larger` is set, and on a tie the requested scale wins.
+        let mut minimal_distance = (false, i32::MAX, false);
+        let mut closest_file: Option<(PathBuf, &IconDirectory, bool)> = None;
 
-        'dir: for subdir in sub_dirs.iter() {
-            let distance = subdir.size_distance(size, scale);
+        'dir: for &(i, subdir) in sub_dirs.iter() {
+            let smaller = options.is_prefer_larger() && subdir.size * subdir.scale < size * scale;
+            let distance = (smaller, subdir.size_distance(size, scale), subdir.scale != scale);
             if distance >= minimal_distance { continue; }
 
-            'location: for basedir in &self.base_dirs {
-                'ext: for ext in BASIC_EXTS {
-                    let p: PathBuf = format!("{}/{}/{}.{}", basedir.display(), subdir.name, name, ext).into();
-
-                    if p.is_file() {
-                        closest_file = Some(p);
+            for base in 0..self.base_dirs.len() {
+                for file in 0..file_names.len() {
+                    if let Some(p) = self.file_exists(&cached, base, i, file_names, file, options) {
+                        closest_file = Some((p, subdir, false));
                         minimal_distance =  distance;
 
                         continue 'dir;
@@ -355,23 +808,40 @@ impl IconTheme {
             }
         }
 
-        if closest_file.is_some() { return closest_file; }
+        closest_file
+    }
 
-        // test in extra dirs
-        let extra = self.extra_dirs.par_iter()
-                        .filter(|x| x.is_dir())
-                        .flat_map(|x| EXTRA_EXTS.par_iter()
-                                        .map_with(x, |x, ext| format!("{}/{}.{}", x.display(), name, ext).into()))
-                        .find_any(|x: &PathBuf| x.is_file());
+    /// List names of all icons in directories of the given `Context`.
+    pub fn list_icons(&self, context: &IconContext) -> Vec<String> {
+
+        let mut r: Vec<String> = self.sub_dirs.iter()
+            .filter(|x| x.context.as_ref() == Some(context))
+            .flat_map(|sub| self.base_dirs.iter().map(move |base| base.join(&sub.name)))
+            .filter_map(|dir| fs::read_dir(dir).ok())
+            .flat_map(|entries| entries.filter_map(|x| x.ok()))
+            .map(|entry| entry.path())
+            .filter(|p| p.extension().and_then(|x| x.to_str()).map(|x| EXTRA_EXTS.contains(&x)).unwrap_or(false))
+            .filter_map(|p| p.file_stem().map(|x| x.to_string_lossy().to_string()))
+            .collect();
+
+        r.sort();
+        r.dedup();
 
-        return extra
+        r
     }
 
     pub fn lookup_fallback_icon(&self, name: &IconName, size: i32, scale: i32) -> Option<PathBuf> {
+        self.lookup_fallback_icon_info(name, size, scale, &IconLookupOptions::default()).map(|x| x.into_path())
+    }
+
+    pub fn lookup_fallback_icon_info(&self, name: &IconName, size: i32, scale: i32, options: &IconLookupOptions) -> Option<IconInfo> {
+
+        if options.is_no_generic_fallback() { return None; }
 
-        let mut fallback = name.clone();
+        let mut fallback = options.apply(name);
         while let Some(fallback) = fallback.fallback() {
-            if let Some(icon) = self.lookup_icon(fallback, size, scale) {
+            if let Some(mut icon) = self.lookup_icon_internal(fallback, None, size, scale, options) {
+                icon.set_fallback(true);
                 return Some(icon);
             }
         }
@@ -400,132 +870,180 @@ impl IconTheme {
 mod test {
     use icon_theme::*;
     use icon_lookup::*;
+    use icon_name::TextDirection;
 
-    use std::env;
     // use test::Bencher;
 
     #[test]
-    fn test_fetch_user_dir() {
-        let _env_lock = TEST_ENV_MUTEX.lock().unwrap();
-
-        env::remove_var("HOME");
-        env::remove_var("XDG_DATA_HOME");
-        env::remove_var("XDG_DATA_DIRS");
+    fn test_app_icon_lookup() {
+        let theme = IconTheme::from_dir("tests/icons/themed").unwrap();
 
-        assert!(get_user_icon_dir().is_empty());
+        assert_eq!(theme.lookup_icon(&"deepin-deb-installer".into(), 32, 1),
+                    Some("tests/icons/themed/apps/32/deepin-deb-installer.svg".into()));
+    }
 
-        env::set_var("HOME", "tests/fake_home");
-        let dirs: Vec<PathBuf> = vec![ "tests/fake_home/.local/share/icons".into() ];
-        assert_eq!(get_user_icon_dir(), dirs);
+    #[test]
+    fn test_lookup_threshold() {
+        let theme = IconTheme::from_dir("tests/icons/hicolor").unwrap();
 
-        env::set_var("XDG_DATA_HOME", "tests/fake_home/.local/share");
-        assert_eq!(get_user_icon_dir(), dirs);
+        assert_eq!(theme.lookup_icon(&"TestAppIcon".into(), 46, 1),
+                    Some("tests/icons/hicolor/apps/48/TestAppIcon.png".into()));
+        assert_eq!(theme.lookup_icon(&"TestAppIcon".into(), 50, 1),
+                    Some("tests/icons/hicolor/apps/48/TestAppIcon.png".into()));
+        assert_eq!(theme.lookup_icon(&"TestAppIcon".into(), 51, 1),
+                    Some("tests/icons/hicolor/apps/scalable/TestAppIcon.svg".into()));
+    }
 
-        env::remove_var("HOME");
-        assert_eq!(get_user_icon_dir(), dirs);
+    #[test]
+    fn test_size_distance() {
+        let dir = |type_, size, scale| IconDirectory { type_, size, scale, ..Default::default() };
+
+        let fixed = dir(DirectoryType::Fixed, 48, 1);
+        assert_eq!(fixed.size_distance(48, 1), 0);
+        assert_eq!(fixed.size_distance(24, 2), 0);
+        assert_eq!(fixed.size_distance(16, 1), 32);
+
+        let threshold = dir(DirectoryType::Threshold(2), 48, 1);
+        assert_eq!(threshold.size_distance(46, 1), 0);
+        assert_eq!(threshold.size_distance(50, 1), 0);
+        assert_eq!(threshold.size_distance(40, 1), 6);
+        assert_eq!(threshold.size_distance(32, 2), 14);
+
+        // min/max are scaled by the directory scale, not the requested scale
+        let scalable = dir(DirectoryType::Scalable(16, 32), 16, 2);
+        assert_eq!(scalable.size_distance(24, 2), 0);
+        assert_eq!(scalable.size_distance(16, 1), 16);
+        assert_eq!(scalable.size_distance(40, 2), 16);
+    }
 
-        env::set_var("XDG_DATA_DIRS", "tests:tests/fake_home/.local/share");
-        let dirs: Vec<PathBuf> = vec![ "tests/icons".into(),
-                                       "tests/fake_home/.local/share/icons".into() ];
-        assert_eq!(get_user_icon_dir(), dirs);
+    #[test]
+    fn test_cross_scale_lookup() {
+        let theme = IconTheme::from_dir("tests/icons/hidpi").unwrap();
+
+        // no @2 directory, served by the 48@1 directory
+        assert_eq!(theme.lookup_icon(&"hidpi-app".into(), 24, 2),
+                    Some("tests/icons/hidpi/48x48/apps/hidpi-app.png".into()));
+        assert_eq!(theme.lookup_icon(&"hidpi-app".into(), 24, 1),
+                    Some("tests/icons/hidpi/24x24/apps/hidpi-app.png".into()));
+
+        // same distance, the requested scale wins
+        assert_eq!(theme.lookup_icon(&"scaled-app".into(), 24, 2),
+                    Some("tests/icons/hidpi/24x24@2/apps/scaled-app.png".into()));
+        assert_eq!(theme.lookup_icon(&"scaled-app".into(), 20, 2),
+                    Some("tests/icons/hidpi/24x24@2/apps/scaled-app.png".into()));
     }
 
     #[test]
-    fn test_app_icon_lookup() {
-        let theme = IconTheme::from_dir("tests/icons/themed").unwrap();
+    fn test_scaled_directories() {
+        let theme = IconTheme::from_dir("tests/icons/hidpi").unwrap();
 
-        assert_eq!(theme.lookup_icon(&"deepin-deb-installer".into(), 32, 1),
-                    Some("tests/icons/themed/apps/32/deepin-deb-installer.svg".into()));
+        let dirs: Vec<(&str, i32)> = theme.sub_dirs.iter().map(|x| (x.name(), x.scale())).collect();
+        assert_eq!(dirs, vec![("24x24/apps", 1), ("48x48/apps", 1), ("24x24@2/apps", 2), ("48x48@2/apps", 2)]);
+
+        assert_eq!(theme.lookup_icon(&"hidpi-app".into(), 48, 2),
+                    Some("tests/icons/hidpi/48x48@2/apps/hidpi-app.png".into()));
     }
 
     #[test]
-    fn test_in_another_base_dir() {
-        let _env_lock = TEST_ENV_MUTEX.lock().unwrap();
+    fn test_dir_index() {
+        let dir = Path::new("target/test-dir-index");
+        scratch_theme::create(dir, &["first"]);
 
-        env::set_var("XDG_DATA_DIRS", "tests:tests/fake_home/.local/share");
+        let theme = IconTheme::from_dir(dir).unwrap().with_dir_index(true);
+        assert_eq!(theme.lookup_icon(&"first".into(), 16, 1), Some(dir.join("apps/16/first.png")));
 
-        // clear cache
-        ICON_THEME_CACHE.lock().unwrap().clear();
+        // the listing is kept until invalidated
+        let second = scratch_theme::install(dir, "second");
+        assert_eq!(theme.lookup_icon(&"second".into(), 16, 1), None);
 
-        test_lookup!("themed", "just-in-another-base", 16, 1
-                    => "tests/fake_home/.local/share/icons/themed/apps/16/just-in-another-base.png");
+        theme.invalidate_dir_index();
+        assert_eq!(theme.lookup_icon(&"second".into(), 16, 1), Some(second));
 
-        // clear cache
-        ICON_THEME_CACHE.lock().unwrap().clear();
+        // same results as probing files
+        let theme = IconTheme::from_dir("tests/icons/symbolic").unwrap().with_dir_index(true);
+        assert_eq!(theme.lookup_icon(&"media-playback-start-symbolic".into(), 16, 1),
+                    Some("tests/icons/symbolic/actions/16/media-playback-start-symbolic.symbolic.png".into()));
+        assert_eq!(theme.lookup_icon(&"go-up-foo".into(), 16, 1), None);
     }
 
     #[test]
-    fn test_icon_theme_lru_cache() {
-        let _env_lock = TEST_ENV_MUTEX.lock().unwrap();
+    fn test_stale_gtk_cache() {
+        use gtk_cache::GtkIconCacheWriter;
+        use self::scratch_theme::set_mtime;
 
-        env::set_var("XDG_DATA_DIRS", "tests");
+        let dir = Path::new("target/test-stale-gtk-cache");
+        scratch_theme::create(dir, &["first"]);
 
-        let mut cache = ICON_THEME_CACHE.lock().unwrap();
-        let capacity = cache.capacity();
-        cache.clear();
-        cache.set_capacity(1);
-        drop(cache);
+        let cache = GtkIconCacheWriter::with_theme_dir(dir).update().unwrap();
+        set_mtime(dir, 1000);
+        set_mtime(&dir.join("apps/16"), 1000);
+        set_mtime(&cache, 2000);
 
-        assert_eq!(0, ICON_THEME_CACHE.lock().unwrap().len());
+        let theme = IconTheme::from_dir(dir).unwrap();
+        assert!(theme.has_gtk_cache());
 
-        test_lookup!("themed", "test", 48, 1
-                    => "tests/icons/themed/apps/48/test.png");
-        // cache should have 1 new item.
-        assert_eq!(1, ICON_THEME_CACHE.lock().unwrap().len());
+        // installed without updating the cache
+        scratch_theme::install(dir, "second");
+        set_mtime(&dir.join("apps/16"), 3000);
 
-        let _ = lookup!("hicolor", "test", 48, 1);
-        assert_eq!(1, ICON_THEME_CACHE.lock().unwrap().len());
-        assert!(ICON_THEME_CACHE.lock().unwrap().contains_key("hicolor"));
-        assert!(!ICON_THEME_CACHE.lock().unwrap().contains_key("themed"));
-
-        // clear cache
-        let mut cache = ICON_THEME_CACHE.lock().unwrap();
-        cache.clear();
-        cache.set_capacity(capacity);
+        let theme = IconTheme::from_dir(dir).unwrap();
+        assert!(!theme.has_gtk_cache());
+        assert_eq!(theme.lookup_icon(&"second".into(), 16, 1), Some(dir.join("apps/16/second.png")));
+        assert_eq!(theme.lookup_icon(&"first".into(), 16, 1), Some(dir.join("apps/16/first.png")));
     }
 
     #[test]
-    fn test_should_not_save_invalid_theme() {
-        let _env_lock = TEST_ENV_MUTEX.lock().unwrap();
-
-        let mut cache = ICON_THEME_CACHE.lock().unwrap();
-        let capacity = cache.capacity();
-        cache.clear();
-        cache.set_capacity(1);
-        drop(cache);
+    fn test_gtk_cache_without_probing() {
+        use gtk_cache::GtkIconCacheWriter;
+        use self::scratch_theme::set_mtime;
 
-        assert_eq!(0, ICON_THEME_CACHE.lock().unwrap().len());
+        let dir = Path::new("target/test-gtk-cache-probing");
+        scratch_theme::create(dir, &["removed"]);
 
-        env::set_var("XDG_DATA_DIRS", "tests");
+        let cache = GtkIconCacheWriter::with_theme_dir(dir).update().unwrap();
+        fs::remove_file(dir.join("apps/16/removed.png")).unwrap();
+        set_mtime(dir, 1000);
+        set_mtime(&dir.join("apps/16"), 1000);
+        set_mtime(&cache, 2000);
 
-        // invalid icon theme should't save
-        test_lookup!("InvalidThemeName", "TestAppIcon", 16, 1
-                    => "tests/icons/hicolor/apps/16/TestAppIcon.png");
-        assert!(!ICON_THEME_CACHE.lock().unwrap().contains_key("InvalidThemeName"));
+        // the cache is trusted, the removed file is never stat'ed
+        let theme = IconTheme::from_dir(dir).unwrap();
+        assert!(theme.has_gtk_cache());
+        assert_eq!(theme.lookup_icon(&"removed".into(), 16, 1), Some(dir.join("apps/16/removed.png")));
 
-        ICON_THEME_CACHE.lock().unwrap().clear();
+        let options = IconLookupOptions::default().verify_files(true);
+        assert!(theme.lookup_icon_info(&"removed".into(), 16, 1, &options).is_none());
 
-        // valid icon theme should be saved
-        test_lookup!("hicolor", "TestAppIcon", 16, 1
-                    => "tests/icons/hicolor/apps/16/TestAppIcon.png");
-        assert!(ICON_THEME_CACHE.lock().unwrap().contains_key("hicolor"));
-
-        // clear cache
-        let mut cache = ICON_THEME_CACHE.lock().unwrap();
-        cache.clear();
-        cache.set_capacity(capacity);
+        let lookup = ::icon_lookup::IconLookup::new(vec!["target"]);
+        assert_eq!(lookup.find_icon_with_theme_name("test-gtk-cache-probing", "removed", 16, 1), Some(dir.join("apps/16/removed.png")));
     }
 
     #[test]
-    fn test_lookup_threshold() {
-        let theme = IconTheme::from_dir("tests/icons/hicolor").unwrap();
+    fn test_theme_metadata() {
+        let theme = IconTheme::from_dir("tests/icons/themed").unwrap();
 
-        assert_eq!(theme.lookup_icon(&"TestAppIcon".into(), 46, 1),
-                    Some("tests/icons/hicolor/apps/48/TestAppIcon.png".into()));
-        assert_eq!(theme.lookup_icon(&"TestAppIcon".into(), 50, 1),
-                    Some("tests/icons/hicolor/apps/48/TestAppIcon.png".into()));
-        assert_eq!(theme.lookup_icon(&"TestAppIcon".into(), 51, 1),
-                    Some("tests/icons/hicolor/apps/scalable/TestAppIcon.svg".into()));
+        assert_eq!(theme.name(), "themed");
+        assert_eq!(theme.comment(), "Test icon theme");
+        assert_eq!(theme.example(), Some("test"));
+        assert_eq!(theme.display_depth(), Some(32));
+        assert!(!theme.is_hidden());
+        assert!(IconTheme::from_dir("tests/icons/chain-c").unwrap().is_hidden());
+
+        assert_eq!(theme.localized_name("de_DE.UTF-8"), "Thema");
+        assert_eq!(theme.localized_name("de_CH"), "Thema (Schweiz)");
+        assert_eq!(theme.localized_name("de_CH.UTF-8@euro"), "Thema (Schweiz)");
+        assert_eq!(theme.localized_name("sr_RS@latin"), "Tema");
+        assert_eq!(theme.localized_name("sr_RS"), "themed");
+        assert_eq!(theme.localized_name("C"), "themed");
+        assert_eq!(theme.localized_name(""), "themed");
+        assert_eq!(theme.localized_comment("de_AT"), "Test-Symbolthema");
+        assert_eq!(theme.localized_comment("fr"), "Test icon theme");
+
+        assert_eq!(theme.default_size(IconGroup::Desktop), Some(48));
+        assert_eq!(theme.sizes(IconGroup::Desktop), &[16, 22, 32, 48]);
+        assert_eq!(theme.default_size(IconGroup::Panel), Some(32));
+        assert!(theme.sizes(IconGroup::Panel).is_empty());
+        assert_eq!(theme.default_size(IconGroup::Toolbar), None);
     }
 
     #[test]
@@ -565,6 +1083,64 @@ mod test {
                     // Some("tests/extra-icons/extraxpm-with-fallback.xpm".into()));
     }
 
+    #[test]
+    fn test_inheritance_chain() {
+        let theme = IconTheme::from_dir("tests/icons/chain-a").unwrap();
+        let chain: Vec<String> = theme.inheritance_chain()
+                                      .iter()
+                                      .map(|x| x.internal_name().to_string())
+                                      .collect();
+
+        assert_eq!(chain, vec!["chain-b", "chain-c"]);
+
+        test_lookup!(theme, "grandparent-icon", 16, 1
+                    => "tests/icons/chain-c/apps/16/grandparent-icon.png");
+    }
+
+    #[test]
+    fn test_lookup_in_context() {
+        let theme = IconTheme::from_dir("tests/icons/themed").unwrap();
+
+        assert_eq!(theme.lookup_icon_in_context(&"deepin-deb-installer".into(), &IconContext::Applications, 32, 1),
+                    Some("tests/icons/themed/apps/32/deepin-deb-installer.svg".into()));
+        assert_eq!(theme.lookup_icon_in_context(&"deepin-deb-installer".into(), &IconContext::MimeTypes, 32, 1),
+                    None);
+
+        assert_eq!(theme.list_icons(&IconContext::Applications),
+                    vec!["deepin-deb-installer", "name.with.dot", "test", "test-48x"]);
+        assert!(theme.list_icons(&IconContext::Places).is_empty());
+    }
+
+    #[test]
+    fn test_directional_lookup() {
+        let theme = IconTheme::from_dir("tests/icons/symbolic").unwrap();
+        let rtl = IconName::from("go-next").with_direction(TextDirection::Rtl);
+        let ltr = IconName::from("go-next").with_direction(TextDirection::Ltr);
+
+        assert_eq!(theme.lookup_icon(&rtl, 16, 1),
+                    Some("tests/icons/symbolic/actions/16/go-next-rtl.png".into()));
+        assert_eq!(theme.lookup_icon(&ltr, 16, 1),
+                    Some("tests/icons/symbolic/actions/16/go-next.png".into()));
+
+        // the direction goes before `-symbolic`, as named by GTK
+        let rtl = IconName::from("go-next-symbolic").with_direction(TextDirection::Rtl);
+        assert_eq!(theme.lookup_icon(&rtl, 16, 1),
+                    Some("tests/icons/symbolic/actions/16/go-next-rtl-symbolic.svg".into()));
+
+        // direction is kept when falling back
+        let rtl = IconName::from("edit-undo-extend").with_direction(TextDirection::Rtl);
+        assert_eq!(theme.lookup_fallback_icon(&rtl, 16, 1),
+                    Some("tests/icons/symbolic/actions/16/edit-undo-rtl.png".into()));
+    }
+
+    #[test]
+    fn test_load_error() {
+        match IconTheme::from_dir("tests/extra-icons") {
+            Err(IconThemeError::IndexNotFound(path)) => assert_eq!(path, PathBuf::from("tests/extra-icons/index.theme")),
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
     // fn bench_test(b: &mut Bencher, gtk_cache: bool) {
 
     //     let _env_lock = TEST_ENV_MUTEX.lock().unwrap();
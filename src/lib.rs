@@ -15,13 +15,37 @@ extern crate lazy_static;
 #[macro_use]
 extern crate log;
 extern crate rayon;
+#[cfg(test)]
 extern crate gtk_icon_cache;
 #[macro_use]
 extern crate bitflags;
+#[cfg(feature = "watch")]
+extern crate libc;
 
 #[macro_use]
 pub mod macros;
+mod error;
 mod icon_theme;
 mod icon_name;
+mod icon_info;
+mod lookup_options;
+mod dir_index;
+mod gtk_cache;
+mod search_path;
+mod theme_name;
+mod theme_cache;
+mod theme_info;
+#[cfg(feature = "watch")]
+mod watcher;
 pub mod ffi;
-pub mod icon_lookup;
\ No newline at end of file
+pub mod icon_lookup;
+
+pub use error::IconThemeError;
+pub use icon_theme::{DirectoryType, IconContext, IconDirectory, IconGroup, IconTheme};
+pub use icon_info::{IconFormat, IconInfo};
+pub use lookup_options::IconLookupOptions;
+pub use gtk_cache::GtkIconCacheWriter;
+pub use icon_name::{FallbackRules, IconName, TextDirection};
+pub use search_path::{SearchPath, SearchPathBuilder};
+pub use theme_info::IconThemeInfo;
+pub use theme_name::{EnvThemeName, GSettingsThemeName, IniThemeName, ThemeNameChain, ThemeNameProvider};
\ No newline at end of file
@@ -0,0 +1,152 @@
+use icon_name::IconName;
+
+static PNG_SVG: &[&str] = &["png", "svg"];
+static SVG_PNG: &[&str] = &["svg", "png"];
+static PNG_ONLY: &[&str] = &["png"];
+static EXTRA_PNG_SVG: &[&str] = &["png", "svg", "xpm"];
+static EXTRA_SVG_PNG: &[&str] = &["svg", "png", "xpm"];
+static EXTRA_PNG_ONLY: &[&str] = &["png", "xpm"];
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    struct LookupFlags: u32 {
+        const FORCE_SVG = 1 << 0;
+        const NO_SVG = 1 << 1;
+        const FORCE_SYMBOLIC = 1 << 2;
+        const FORCE_REGULAR = 1 << 3;
+        const NO_GENERIC_FALLBACK = 1 << 4;
+        const NO_FALLBACK_THEME = 1 << 5;
+        const NO_EXTRA_DIRS = 1 << 6;
+        const PREFER_LARGER = 1 << 7;
+        const VERIFY_FILES = 1 << 8;
+    }
+}
+
+///
+/// Options to tune the lookup algorithm, modeled on `GtkIconLookupFlags`.
+///
+/// ```no_run
+/// use themed_icon_lookup::IconLookupOptions;
+/// use themed_icon_lookup::icon_lookup::find_icon_info;
+///
+/// let options = IconLookupOptions::new()
+///                 .force_symbolic(true)
+///                 .no_extra_dirs(true);
+///
+/// let icon = find_icon_info("go-next", 16, 1, &options);
+/// ```
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IconLookupOptions {
+    flags: LookupFlags,
+}
+
+impl Default for IconLookupOptions {
+    fn default() -> Self {
+        Self { flags: LookupFlags::empty() }
+    }
+}
+
+macro_rules! option_flag {
+    ($(#[$doc: meta])* $setter: ident, $getter: ident, $flag: ident) => {
+        $(#[$doc])*
+        pub fn $setter(mut self, enable: bool) -> Self {
+            self.flags.set(LookupFlags::$flag, enable);
+            self
+        }
+
+        pub fn $getter(&self) -> bool {
+            self.flags.contains(LookupFlags::$flag)
+        }
+    };
+}
+
+impl IconLookupOptions {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    option_flag!(
+        /// Prefer SVG icons even if a PNG is available.
+        force_svg, is_force_svg, FORCE_SVG);
+    option_flag!(
+        /// Never return SVG icons.
+        no_svg, is_no_svg, NO_SVG);
+    option_flag!(
+        /// Try symbolic icons first, even for a full-color name.
+        force_symbolic, is_force_symbolic, FORCE_SYMBOLIC);
+    option_flag!(
+        /// Try full-color icons first, even for a `-symbolic` name.
+        force_regular, is_force_regular, FORCE_REGULAR);
+    option_flag!(
+        /// Don't strip dash-separated segments of the name.
+        no_generic_fallback, is_no_generic_fallback, NO_GENERIC_FALLBACK);
+    option_flag!(
+        /// Don't search the fallback theme, `hicolor` by default.
+        no_fallback_theme, is_no_fallback_theme, NO_FALLBACK_THEME);
+    option_flag!(
+        /// Don't search extra dirs like `/usr/share/pixmaps`.
+        no_extra_dirs, is_no_extra_dirs, NO_EXTRA_DIRS);
+    option_flag!(
+        /// If no directory matches the size, prefer the closest larger one over a smaller one.
+        prefer_larger, is_prefer_larger, PREFER_LARGER);
+    option_flag!(
+        /// Check files recorded in `icon-theme.cache` exist instead of trusting the cache.
+        verify_files, is_verify_files, VERIFY_FILES);
+
+    /// Extensions probed in theme directories, by priority.
+    pub(crate) fn exts(&self) -> &'static [&'static str] {
+        if self.is_no_svg() {
+            PNG_ONLY
+        } else if self.is_force_svg() {
+            SVG_PNG
+        } else {
+            PNG_SVG
+        }
+    }
+
+    /// Extensions probed in extra dirs, by priority.
+    pub(crate) fn extra_exts(&self) -> &'static [&'static str] {
+        if self.is_no_svg() {
+            EXTRA_PNG_ONLY
+        } else if self.is_force_svg() {
+            EXTRA_SVG_PNG
+        } else {
+            EXTRA_PNG_SVG
+        }
+    }
+
+    /// Apply `force_symbolic`/`force_regular` to the name.
+    pub(crate) fn apply(&self, name: &IconName) -> IconName {
+        if self.is_force_symbolic() {
+            name.force_symbolic()
+        } else if self.is_force_regular() {
+            name.force_regular()
+        } else {
+            name.clone()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use lookup_options::*;
+
+    #[test]
+    fn test_options_builder() {
+        let options = IconLookupOptions::new().no_svg(true).prefer_larger(true);
+
+        assert!(options.is_no_svg());
+        assert!(options.is_prefer_larger());
+        assert!(!options.is_force_svg());
+        assert_eq!(options.exts(), &["png"]);
+        assert_eq!(options.no_svg(false).exts(), &["png", "svg"]);
+        assert_eq!(IconLookupOptions::new().force_svg(true).extra_exts(), &["svg", "png", "xpm"]);
+
+        let name = IconLookupOptions::new().force_symbolic(true).apply(&"go-next".into());
+        assert_eq!(name.name(), "go-next-symbolic");
+
+        let name = IconLookupOptions::new().force_regular(true).apply(&"go-next-symbolic".into());
+        assert_eq!(name.name(), "go-next");
+    }
+}
@@ -0,0 +1,195 @@
+use std::env;
+use std::path::PathBuf;
+
+///
+/// Resolved icon search path.
+///
+/// `base_dirs` contain icon themes, `extra_dirs` contain plain icon files
+/// and are searched after all themes, like `/usr/share/pixmaps`.
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SearchPath {
+    base_dirs: Vec<PathBuf>,
+    extra_dirs: Vec<PathBuf>,
+}
+
+impl SearchPath {
+    /// Search path of the current environment.
+    pub fn from_env() -> Self {
+        SearchPathBuilder::from_env().build()
+    }
+
+    pub fn base_dirs(&self) -> &[PathBuf] {
+        &self.base_dirs
+    }
+
+    pub fn extra_dirs(&self) -> &[PathBuf] {
+        &self.extra_dirs
+    }
+}
+
+///
+/// Build a `SearchPath` in the order of the icon theme spec:
+///
+/// 1. `$HOME/.icons`
+/// 2. `$XDG_DATA_HOME/icons`, `$HOME/.local/share/icons` if not set
+/// 3. each `$XDG_DATA_DIRS/icons`, `/usr/local/share:/usr/share` if not set
+/// 4. `/usr/share/pixmaps`
+///
+#[derive(Debug, Clone)]
+pub struct SearchPathBuilder {
+    home: Option<PathBuf>,
+    data_home: Option<PathBuf>,
+    data_dirs: Vec<PathBuf>,
+    extra_dirs: Vec<PathBuf>,
+}
+
+impl Default for SearchPathBuilder {
+    fn default() -> Self {
+        Self {
+            home: None,
+            data_home: None,
+            data_dirs: vec!["/usr/local/share".into(), "/usr/share".into()],
+            extra_dirs: vec!["/usr/share/pixmaps".into()],
+        }
+    }
+}
+
+fn non_empty_var(key: &str) -> Option<String> {
+    env::var(key).ok().filter(|x| !x.is_empty())
+}
+
+impl SearchPathBuilder {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Read `HOME`, `XDG_DATA_HOME` and `XDG_DATA_DIRS`, empty values are treated as unset.
+    pub fn from_env() -> Self {
+        let mut r = Self::new();
+
+        r.home = non_empty_var("HOME").map(|x| x.into());
+        r.data_home = non_empty_var("XDG_DATA_HOME").map(|x| x.into());
+
+        if let Some(dirs) = non_empty_var("XDG_DATA_DIRS") {
+            r.data_dirs = dirs.split(':')
+                              .filter(|x| !x.is_empty())
+                              .map(|x| x.into())
+                              .collect();
+        }
+
+        r
+    }
+
+    pub fn with_home<T: Into<PathBuf>>(mut self, home: T) -> Self {
+        self.home = Some(home.into());
+        self
+    }
+
+    pub fn with_data_home<T: Into<PathBuf>>(mut self, data_home: T) -> Self {
+        self.data_home = Some(data_home.into());
+        self
+    }
+
+    pub fn with_data_dirs<T: Into<PathBuf>>(mut self, data_dirs: Vec<T>) -> Self {
+        self.data_dirs = data_dirs.into_iter().map(|x| x.into()).collect();
+        self
+    }
+
+    /// Replace the default `/usr/share/pixmaps`.
+    pub fn with_extra_dirs<T: Into<PathBuf>>(mut self, extra_dirs: Vec<T>) -> Self {
+        self.extra_dirs = extra_dirs.into_iter().map(|x| x.into()).collect();
+        self
+    }
+
+    pub fn build(&self) -> SearchPath {
+        let mut base_dirs = vec![];
+
+        if let Some(ref home) = self.home {
+            base_dirs.push(home.join(".icons"));
+        }
+
+        match (self.data_home.as_ref(), self.home.as_ref()) {
+            (Some(data_home), _) => base_dirs.push(data_home.join("icons")),
+            (None, Some(home)) => base_dirs.push(home.join(".local/share/icons")),
+            _ => {},
+        }
+
+        base_dirs.extend(self.data_dirs.iter().map(|x| x.join("icons")));
+
+        SearchPath {
+            base_dirs: dedup(base_dirs),
+            extra_dirs: dedup(self.extra_dirs.clone()),
+        }
+    }
+}
+
+fn dedup(dirs: Vec<PathBuf>) -> Vec<PathBuf> {
+    let mut r: Vec<PathBuf> = Vec::with_capacity(dirs.len());
+
+    for dir in dirs {
+        if !r.contains(&dir) {
+            r.push(dir);
+        }
+    }
+
+    r
+}
+
+#[cfg(test)]
+mod test {
+    use search_path::*;
+    use icon_theme::TEST_ENV_MUTEX;
+
+    #[test]
+    fn test_search_path_order() {
+        let search_path = SearchPathBuilder::new()
+                            .with_home("/home/user")
+                            .with_data_dirs(vec!["/usr/share", "/opt/share", "/usr/share"])
+                            .build();
+
+        let dirs: Vec<PathBuf> = vec!["/home/user/.icons".into(),
+                                      "/home/user/.local/share/icons".into(),
+                                      "/usr/share/icons".into(),
+                                      "/opt/share/icons".into()];
+        assert_eq!(search_path.base_dirs(), &dirs[..]);
+        assert_eq!(search_path.extra_dirs(), &[PathBuf::from("/usr/share/pixmaps")]);
+    }
+
+    #[test]
+    fn test_fetch_user_dir() {
+        let _env_lock = TEST_ENV_MUTEX.lock().unwrap();
+
+        env::remove_var("HOME");
+        env::remove_var("XDG_DATA_HOME");
+        env::remove_var("XDG_DATA_DIRS");
+
+        let dirs: Vec<PathBuf> = vec!["/usr/local/share/icons".into(), "/usr/share/icons".into()];
+        assert_eq!(SearchPath::from_env().base_dirs(), &dirs[..]);
+
+        env::set_var("HOME", "tests/fake_home");
+        let dirs: Vec<PathBuf> = vec!["tests/fake_home/.icons".into(),
+                                      "tests/fake_home/.local/share/icons".into(),
+                                      "/usr/local/share/icons".into(),
+                                      "/usr/share/icons".into()];
+        assert_eq!(SearchPath::from_env().base_dirs(), &dirs[..]);
+
+        env::set_var("XDG_DATA_HOME", "tests/fake_home/.local/share");
+        assert_eq!(SearchPath::from_env().base_dirs(), &dirs[..]);
+
+        env::remove_var("HOME");
+        assert_eq!(SearchPath::from_env().base_dirs(), &dirs[1..]);
+
+        // XDG_DATA_HOME is searched before XDG_DATA_DIRS
+        env::set_var("XDG_DATA_DIRS", "tests:tests/fake_home/.local/share");
+        let dirs: Vec<PathBuf> = vec!["tests/fake_home/.local/share/icons".into(),
+                                      "tests/icons".into()];
+        assert_eq!(SearchPath::from_env().base_dirs(), &dirs[..]);
+
+        // empty values are treated as unset
+        env::set_var("XDG_DATA_HOME", "");
+        env::set_var("XDG_DATA_DIRS", "");
+        let dirs: Vec<PathBuf> = vec!["/usr/local/share/icons".into(), "/usr/share/icons".into()];
+        assert_eq!(SearchPath::from_env().base_dirs(), &dirs[..]);
+    }
+}
@@ -0,0 +1,292 @@
+use icon_theme::IconTheme;
+use error::IconThemeError;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Condvar, Mutex, RwLock};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[derive(Debug)]
+struct Entry {
+    theme: Arc<IconTheme>,
+    /// tick of the last hit, the smallest one is evicted first
+    last_used: AtomicUsize,
+}
+
+///
+/// A theme being loaded by one thread, other threads asking for the same
+/// name wait for it instead of parsing the theme again.
+///
+#[derive(Debug, Default)]
+struct Loading {
+    /// `Some(None)` if loading failed
+    result: Mutex<Option<Option<Arc<IconTheme>>>>,
+    done: Condvar,
+}
+
+impl Loading {
+    fn finish(&self, theme: Option<Arc<IconTheme>>) {
+        let mut result = self.result.lock().unwrap();
+        if result.is_none() {
+            *result = Some(theme);
+            self.done.notify_all();
+        }
+    }
+
+    fn wait(&self) -> Option<Arc<IconTheme>> {
+        let mut result = self.result.lock().unwrap();
+        while result.is_none() {
+            result = self.done.wait(result).unwrap();
+        }
+
+        result.clone().unwrap()
+    }
+}
+
+/// Wakes up waiters even if loading panics.
+struct LoadingGuard<'a> {
+    cache: &'a ThemeCache,
+    name: &'a str,
+    loading: Arc<Loading>,
+}
+
+impl<'a> Drop for LoadingGuard<'a> {
+    fn drop(&mut self) {
+        self.cache.loading.lock().unwrap().remove(self.name);
+        self.loading.finish(None);
+    }
+}
+
+///
+/// LRU cache of loaded themes shared by threads.
+///
+/// Hits take only a read lock, themes are loaded outside of any lock and
+/// concurrent requests for one name share a single load. Errors can't be
+/// shared, so waiters of a failed load try once on their own.
+///
+#[derive(Debug)]
+pub(crate) struct ThemeCache {
+    entries: RwLock<HashMap<String, Entry>>,
+    loading: Mutex<HashMap<String, Arc<Loading>>>,
+    capacity: AtomicUsize,
+    tick: AtomicUsize,
+    /// bumped by removals, loads started before are not cached
+    generation: AtomicUsize,
+}
+
+impl ThemeCache {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            entries: RwLock::new(HashMap::new()),
+            loading: Mutex::new(HashMap::new()),
+            capacity: AtomicUsize::new(capacity),
+            tick: AtomicUsize::new(0),
+            generation: AtomicUsize::new(0),
+        }
+    }
+
+    fn hit(&self, name: &str) -> Option<Arc<IconTheme>> {
+        let entries = self.entries.read().unwrap();
+        let entry = entries.get(name)?;
+        entry.last_used.store(self.tick.fetch_add(1, Ordering::Relaxed), Ordering::Relaxed);
+
+        Some(entry.theme.clone())
+    }
+
+    /// The cached theme, or the result of `load` which is cached if it succeeds.
+    pub(crate) fn get_or_load<F>(&self, name: &str, load: F) -> Result<Arc<IconTheme>, IconThemeError>
+      where F: FnOnce() -> Result<IconTheme, IconThemeError> {
+
+        if let Some(theme) = self.hit(name) {
+            return Ok(theme);
+        }
+
+        let (loading, owner) = {
+            let mut loading = self.loading.lock().unwrap();
+
+            // loaded while waiting for the lock
+            if let Some(theme) = self.hit(name) {
+                return Ok(theme);
+            }
+
+            match loading.get(name) {
+                Some(x) => (x.clone(), false),
+                None => {
+                    let x = Arc::new(Loading::default());
+                    loading.insert(name.to_string(), x.clone());
+                    (x, true)
+                },
+            }
+        };
+
+        if !owner {
+            return match loading.wait() {
+                Some(theme) => Ok(theme),
+                None => load().map(Arc::new),
+            };
+        }
+
+        let guard = LoadingGuard { cache: self, name, loading };
+        let generation = self.generation.load(Ordering::SeqCst);
+        let theme = load().map(Arc::new)?;
+
+        if generation == self.generation.load(Ordering::SeqCst) {
+            self.insert(name, theme.clone());
+        }
+        guard.loading.finish(Some(theme.clone()));
+
+        Ok(theme)
+    }
+
+    fn insert(&self, name: &str, theme: Arc<IconTheme>) {
+        let capacity = self.capacity.load(Ordering::Relaxed);
+        let mut entries = self.entries.write().unwrap();
+
+        entries.insert(name.to_string(), Entry {
+            theme,
+            last_used: AtomicUsize::new(self.tick.fetch_add(1, Ordering::Relaxed)),
+        });
+        evict(&mut entries, capacity);
+    }
+
+    pub(crate) fn remove(&self, name: &str) -> bool {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        self.entries.write().unwrap().remove(name).is_some()
+    }
+
+    pub(crate) fn clear(&self) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        self.entries.write().unwrap().clear();
+    }
+
+    pub(crate) fn capacity(&self) -> usize {
+        self.capacity.load(Ordering::Relaxed)
+    }
+
+    /// Least recently used themes are dropped if there are more than `capacity`.
+    pub(crate) fn set_capacity(&self, capacity: usize) {
+        let mut entries = self.entries.write().unwrap();
+        self.capacity.store(capacity, Ordering::Relaxed);
+        evict(&mut entries, capacity);
+    }
+
+    /// Threads waiting for another thread to load `name`.
+    #[cfg(test)]
+    pub(crate) fn waiters(&self, name: &str) -> usize {
+        // one reference is held by `loading`, one by the loading thread
+        self.loading.lock().unwrap().get(name).map(|x| Arc::strong_count(x) - 2).unwrap_or(0)
+    }
+
+    /// Cached themes, most recently used first.
+    pub(crate) fn themes(&self) -> Vec<(String, Arc<IconTheme>)> {
+        let entries = self.entries.read().unwrap();
+        let mut r: Vec<(usize, String, Arc<IconTheme>)> = entries.iter()
+            .map(|(k, v)| (v.last_used.load(Ordering::Relaxed), k.clone(), v.theme.clone()))
+            .collect();
+        r.sort_by_key(|x| ::std::cmp::Reverse(x.0));
+
+        r.into_iter().map(|(_, k, v)| (k, v)).collect()
+    }
+}
+
+fn evict(entries: &mut HashMap<String, Entry>, capacity: usize) {
+    while entries.len() > capacity {
+        let lru = entries.iter()
+                         .min_by_key(|x| x.1.last_used.load(Ordering::Relaxed))
+                         .map(|x| x.0.clone());
+
+        match lru {
+            Some(name) => { entries.remove(&name); },
+            None => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use theme_cache::*;
+
+    use std::sync::{mpsc, Barrier};
+    use std::thread;
+    use std::time::Duration;
+
+    fn load(name: &str) -> Result<IconTheme, IconThemeError> {
+        IconTheme::from_name_in_dirs(name, &["tests/icons".into()], &[])
+    }
+
+    #[test]
+    fn test_lru() {
+        let cache = ThemeCache::new(2);
+
+        cache.get_or_load("themed", || load("themed")).unwrap();
+        cache.get_or_load("hicolor", || load("hicolor")).unwrap();
+        cache.get_or_load("themed", || panic!("should be cached")).unwrap();
+        cache.get_or_load("chain-a", || load("chain-a")).unwrap();
+
+        let names = || -> Vec<String> { cache.themes().into_iter().map(|x| x.0).collect() };
+        assert_eq!(names(), vec!["chain-a", "themed"]);
+
+        cache.set_capacity(1);
+        assert_eq!(names(), vec!["chain-a"]);
+
+        assert!(cache.get_or_load("InvalidThemeName", || load("InvalidThemeName")).is_err());
+        assert_eq!(names(), vec!["chain-a"]);
+
+        assert!(cache.remove("chain-a"));
+        assert!(!cache.remove("chain-a"));
+        assert!(names().is_empty());
+    }
+
+    #[test]
+    fn test_shared_load() {
+        let cache = Arc::new(ThemeCache::new(8));
+        let loads = Arc::new(AtomicUsize::new(0));
+        let barrier = Arc::new(Barrier::new(4));
+
+        let threads: Vec<_> = (0..4).map(|_| {
+            let (cache, loads, barrier) = (cache.clone(), loads.clone(), barrier.clone());
+
+            thread::spawn(move || {
+                barrier.wait();
+                cache.get_or_load("themed", || {
+                    loads.fetch_add(1, Ordering::SeqCst);
+                    // slow disk
+                    thread::sleep(Duration::from_millis(100));
+                    load("themed")
+                }).unwrap()
+            })
+        }).collect();
+
+        let themes: Vec<Arc<IconTheme>> = threads.into_iter().map(|x| x.join().unwrap()).collect();
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+        assert!(themes.iter().all(|x| Arc::ptr_eq(x, &themes[0])));
+
+        // hits don't wait for other loads
+        let (started_tx, started_rx) = mpsc::channel();
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+        let slow = {
+            let cache = cache.clone();
+            thread::spawn(move || cache.get_or_load("hicolor", move || {
+                started_tx.send(()).unwrap();
+                release_rx.recv().unwrap();
+                load("hicolor")
+            }).unwrap())
+        };
+        started_rx.recv().unwrap();
+
+        let (hit_tx, hit_rx) = mpsc::channel();
+        {
+            let cache = cache.clone();
+            thread::spawn(move || {
+                let theme = cache.get_or_load("themed", || panic!("should be cached")).unwrap();
+                hit_tx.send(theme).unwrap();
+            });
+        }
+        // the loader is still blocked until released below
+        let theme = hit_rx.recv_timeout(Duration::from_secs(10)).expect("hit waited for another load");
+        assert!(Arc::ptr_eq(&theme, &themes[0]));
+
+        release_tx.send(()).unwrap();
+        slow.join().unwrap();
+        cache.get_or_load("hicolor", || panic!("should be cached")).unwrap();
+    }
+}
@@ -0,0 +1,140 @@
+use icon_theme::ThemeHeader;
+
+use ini::Ini;
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+///
+/// An installed icon theme, as listed by `list_icon_themes`.
+///
+/// Only the `[Icon Theme]` group of `index.theme` is read, the theme
+/// directories are not parsed.
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IconThemeInfo {
+    internal_name: String,
+    header: ThemeHeader,
+    dirs: Vec<PathBuf>,
+}
+
+impl IconThemeInfo {
+    /// Name of the theme directory, used to load the theme.
+    pub fn internal_name(&self) -> &str {
+        &self.internal_name
+    }
+
+    /// `Name`, the name to show to users.
+    pub fn name(&self) -> &str {
+        &self.header.name
+    }
+
+    pub fn comment(&self) -> &str {
+        &self.header.comment
+    }
+
+    /// `Hidden`, the theme should not be shown in theme choosers.
+    pub fn is_hidden(&self) -> bool {
+        self.header.hidden
+    }
+
+    /// `Example`, name of an icon representing the theme.
+    pub fn example(&self) -> Option<&str> {
+        self.header.example.as_deref()
+    }
+
+    pub fn parents(&self) -> &[String] {
+        &self.header.inherits
+    }
+
+    /// Directories the theme is found in, the first one contains `index.theme`.
+    pub fn dirs(&self) -> &[PathBuf] {
+        &self.dirs
+    }
+}
+
+///
+/// Themes in `base_dirs` sorted by internal name.
+///
+/// A theme split across base dirs is listed once with all its dirs, themes
+/// without `index.theme` in any dir, like cursor themes, are skipped.
+///
+pub(crate) fn list_icon_themes_in_dirs(base_dirs: &[PathBuf]) -> Vec<IconThemeInfo> {
+    let mut themes: BTreeMap<String, Vec<PathBuf>> = BTreeMap::new();
+
+    for base in base_dirs {
+        let entries = match fs::read_dir(base) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+
+        for entry in entries.filter_map(|x| x.ok()) {
+            let dir = entry.path();
+            if !dir.is_dir() { continue; }
+
+            if let Some(name) = entry.file_name().to_str() {
+                let dirs = themes.entry(name.to_string()).or_default();
+                if !dirs.contains(&dir) {
+                    dirs.push(dir);
+                }
+            }
+        }
+    }
+
+    themes.into_iter().filter_map(|(internal_name, mut dirs)| {
+        let i = dirs.iter().position(|x| x.join("index.theme").is_file())?;
+        let index = dirs[i].join("index.theme");
+
+        // the dir with `index.theme` first, like `IconTheme::from_name`
+        let dir = dirs.remove(i);
+        dirs.insert(0, dir);
+
+        let f = match Ini::load_from_file(&index) {
+            Ok(f) => f,
+            Err(e) => {
+                warn!("failed to parse {}: {}", index.display(), e);
+                return None;
+            },
+        };
+
+        let mut r = IconThemeInfo { internal_name, dirs, ..Default::default() };
+
+        if let Some(properties) = f.section(Some("Icon Theme")) {
+            r.header = ThemeHeader::from_properties(properties);
+        }
+
+        Some(r)
+    }).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use theme_info::*;
+
+    #[test]
+    fn test_list_icon_themes() {
+        let themes = list_icon_themes_in_dirs(&["tests/icons".into(), "tests/fake_home/.local/share/icons".into()]);
+        let names: Vec<&str> = themes.iter().map(|x| x.internal_name()).collect();
+
+        assert_eq!(names, vec!["big", "chain-a", "chain-b", "chain-c", "hicolor", "hidpi", "symbolic", "themed"]);
+
+        let themed = themes.iter().find(|x| x.internal_name() == "themed").unwrap();
+        assert_eq!(themed.name(), "themed");
+        assert_eq!(themed.comment(), "Test icon theme");
+        assert_eq!(themed.example(), Some("test"));
+        assert_eq!(themed.parents(), &["parent".to_string()]);
+        assert_eq!(themed.dirs(), &[PathBuf::from("tests/icons/themed"), PathBuf::from("tests/fake_home/.local/share/icons/themed")]);
+        assert!(!themed.is_hidden());
+
+        let chain_c = themes.iter().find(|x| x.internal_name() == "chain-c").unwrap();
+        assert_eq!(chain_c.name(), "Chain C");
+        assert_eq!(chain_c.example(), None);
+        assert!(chain_c.is_hidden());
+
+        // `index.theme` in a later base dir
+        let themes = list_icon_themes_in_dirs(&["tests/fake_home/.local/share/icons".into(), "tests/icons".into()]);
+        let themed = themes.iter().find(|x| x.internal_name() == "themed").unwrap();
+        assert_eq!(themed.dirs()[0], PathBuf::from("tests/icons/themed"));
+    }
+}
@@ -0,0 +1,232 @@
+use ini::Ini;
+
+use std::env;
+use std::path::PathBuf;
+use std::process::Command;
+
+///
+/// Source of the default icon theme name.
+///
+pub trait ThemeNameProvider: Send + Sync {
+    fn theme_name(&self) -> Option<String>;
+}
+
+///
+/// Read the theme name from an environment variable.
+///
+#[derive(Debug, Clone)]
+pub struct EnvThemeName {
+    key: String,
+}
+
+impl EnvThemeName {
+    pub fn new<T: AsRef<str>>(key: T) -> Self {
+        Self { key: key.as_ref().to_string() }
+    }
+}
+
+impl Default for EnvThemeName {
+    fn default() -> Self {
+        Self::new("TIL_ICON_THEME")
+    }
+}
+
+impl ThemeNameProvider for EnvThemeName {
+    fn theme_name(&self) -> Option<String> {
+        env::var(&self.key).ok().filter(|x| !x.is_empty())
+    }
+}
+
+///
+/// Read the theme name from a key of an INI style config file,
+/// like GTK's `settings.ini` or KDE's `kdeglobals`.
+///
+#[derive(Debug, Clone)]
+pub struct IniThemeName {
+    path: PathBuf,
+    section: String,
+    key: String,
+}
+
+impl IniThemeName {
+    pub fn new<P: Into<PathBuf>, T: AsRef<str>>(path: P, section: T, key: T) -> Self {
+        Self {
+            path: path.into(),
+            section: section.as_ref().to_string(),
+            key: key.as_ref().to_string(),
+        }
+    }
+
+    /// `gtk-icon-theme-name` of GTK `settings.ini`.
+    pub fn gtk<P: Into<PathBuf>>(path: P) -> Self {
+        Self::new(path, "Settings", "gtk-icon-theme-name")
+    }
+
+    /// `[Icons] Theme=` of KDE `kdeglobals`.
+    pub fn kde<P: Into<PathBuf>>(path: P) -> Self {
+        Self::new(path, "Icons", "Theme")
+    }
+}
+
+impl ThemeNameProvider for IniThemeName {
+    fn theme_name(&self) -> Option<String> {
+        if !self.path.is_file() { return None; }
+
+        let f = match Ini::load_from_file_noescape(&self.path) {
+            Ok(f) => f,
+            Err(e) => {
+                debug!("failed to load {}: {}", self.path.display(), e);
+                return None;
+            },
+        };
+
+        f.section(Some(self.section.as_str()))
+         .and_then(|x| x.get(self.key.as_str()))
+         .map(|x| x.trim().trim_matches('"').to_string())
+         .filter(|x| !x.is_empty())
+    }
+}
+
+///
+/// Ask `gsettings` for `org.gnome.desktop.interface icon-theme`.
+///
+#[derive(Debug, Clone, Default)]
+pub struct GSettingsThemeName;
+
+impl ThemeNameProvider for GSettingsThemeName {
+    fn theme_name(&self) -> Option<String> {
+        let result = Command::new("gsettings")
+                        .arg("get")
+                        .arg("org.gnome.desktop.interface")
+                        .arg("icon-theme")
+                        .output();
+
+        let result = match result {
+            Ok(r) => r,
+            Err(e) => {
+                debug!("failed to run gsettings: {}", e);
+                return None;
+            },
+        };
+
+        if !result.status.success() { return None; }
+
+        let name = String::from_utf8_lossy(&result.stdout);
+        let name = name.trim().trim_matches('\'');
+
+        if name.is_empty() { None } else { Some(name.to_string()) }
+    }
+}
+
+///
+/// Ordered list of `ThemeNameProvider`s, the first provided name wins
+/// and `hicolor` is used if none of them provides a name.
+///
+#[derive(Default)]
+pub struct ThemeNameChain {
+    providers: Vec<Box<dyn ThemeNameProvider>>,
+}
+
+impl ThemeNameChain {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    ///
+    /// Default providers of the current environment:
+    ///
+    /// 1. `$TIL_ICON_THEME`
+    /// 2. GTK3 and GTK4 `settings.ini` in `$XDG_CONFIG_HOME` then `$XDG_CONFIG_DIRS`
+    /// 3. KDE `kdeglobals` in the same dirs
+    /// 4. `gsettings`, only if the `gsettings` feature is enabled
+    ///
+    pub fn from_env() -> Self {
+        let config_dirs = config_dirs();
+        let mut r = Self::new().with_provider(EnvThemeName::default());
+
+        for dir in config_dirs.iter() {
+            r = r.with_provider(IniThemeName::gtk(dir.join("gtk-3.0/settings.ini")))
+                 .with_provider(IniThemeName::gtk(dir.join("gtk-4.0/settings.ini")));
+        }
+
+        for dir in config_dirs.iter() {
+            r = r.with_provider(IniThemeName::kde(dir.join("kdeglobals")));
+        }
+
+        if cfg!(feature = "gsettings") {
+            r = r.with_provider(GSettingsThemeName);
+        }
+
+        r
+    }
+
+    pub fn with_provider<T: ThemeNameProvider + 'static>(mut self, provider: T) -> Self {
+        self.providers.push(Box::new(provider));
+        self
+    }
+
+    pub fn theme_name(&self) -> String {
+        self.providers.iter()
+            .filter_map(|x| x.theme_name())
+            .next()
+            .unwrap_or_else(|| "hicolor".to_string())
+    }
+}
+
+fn config_dirs() -> Vec<PathBuf> {
+    let mut r = vec![];
+
+    match env::var("XDG_CONFIG_HOME").ok().filter(|x| !x.is_empty()) {
+        Some(dir) => r.push(dir.into()),
+        None => if let Ok(home) = env::var("HOME") {
+            r.push(PathBuf::from(home).join(".config"));
+        },
+    }
+
+    let dirs = env::var("XDG_CONFIG_DIRS").ok().filter(|x| !x.is_empty()).unwrap_or_else(|| "/etc/xdg".to_string());
+    r.extend(dirs.split(':').filter(|x| !x.is_empty()).map(PathBuf::from));
+
+    r
+}
+
+#[cfg(test)]
+mod test {
+    use theme_name::*;
+    use icon_theme::TEST_ENV_MUTEX;
+
+    #[test]
+    fn test_ini_theme_name() {
+        assert_eq!(IniThemeName::gtk("tests/config/gtk-3.0/settings.ini").theme_name(), Some("themed".to_string()));
+        assert_eq!(IniThemeName::kde("tests/config/kdeglobals").theme_name(), Some("breeze".to_string()));
+        assert_eq!(IniThemeName::gtk("tests/config/gtk-4.0/settings.ini").theme_name(), None);
+        assert_eq!(IniThemeName::kde("tests/config/gtk-3.0/settings.ini").theme_name(), None);
+    }
+
+    #[test]
+    fn test_theme_name_chain() {
+        assert_eq!(ThemeNameChain::new().theme_name(), "hicolor");
+
+        let chain = ThemeNameChain::new()
+                        .with_provider(IniThemeName::gtk("tests/config/gtk-4.0/settings.ini"))
+                        .with_provider(IniThemeName::kde("tests/config/kdeglobals"))
+                        .with_provider(IniThemeName::gtk("tests/config/gtk-3.0/settings.ini"));
+        assert_eq!(chain.theme_name(), "breeze");
+    }
+
+    #[test]
+    fn test_env_theme_name() {
+        let _env_lock = TEST_ENV_MUTEX.lock().unwrap();
+
+        env::set_var("XDG_CONFIG_HOME", "tests/config");
+        env::set_var("XDG_CONFIG_DIRS", "tests/no-such-dir");
+        env::remove_var("TIL_ICON_THEME");
+        assert_eq!(ThemeNameChain::from_env().theme_name(), "themed");
+
+        env::set_var("TIL_ICON_THEME", "symbolic");
+        assert_eq!(ThemeNameChain::from_env().theme_name(), "symbolic");
+
+        env::remove_var("TIL_ICON_THEME");
+        env::remove_var("XDG_CONFIG_HOME");
+        env::remove_var("XDG_CONFIG_DIRS");
+    }
+}
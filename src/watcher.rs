@@ -0,0 +1,473 @@
+use icon_theme::IconTheme;
+
+use libc;
+
+use std::collections::{HashMap, HashSet};
+use std::ffi::CString;
+use std::fmt;
+use std::io;
+use std::mem;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::RawFd;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::str;
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+
+const WATCH_MASK: u32 = libc::IN_CREATE | libc::IN_DELETE | libc::IN_MOVED_FROM | libc::IN_MOVED_TO
+                      | libc::IN_CLOSE_WRITE | libc::IN_DELETE_SELF | libc::IN_MOVE_SELF | libc::IN_ONLYDIR;
+
+type Callback = Arc<dyn Fn(&str) + Send + Sync>;
+
+/// What a watched directory is to the lookup.
+#[derive(Debug)]
+enum Watch {
+    /// contains themes, names of changed entries are theme names
+    BaseDir,
+    /// belongs to these themes
+    Theme(HashSet<String>),
+}
+
+/// Themes changed since the last `take_changes`.
+#[derive(Debug, Default)]
+pub(crate) struct Changes {
+    /// events were lost, any theme may have changed
+    pub(crate) all: bool,
+    pub(crate) themes: HashSet<String>,
+}
+
+#[derive(Default)]
+struct WatchState {
+    watches: Mutex<HashMap<i32, Watch>>,
+    changes: Mutex<Changes>,
+    callbacks: Arc<Mutex<Vec<Callback>>>,
+    /// dropped from a callback, the thread closes the fds when it exits
+    detached: AtomicBool,
+}
+
+impl WatchState {
+    /// Themes affected by a buffer of `inotify_event`.
+    fn parse_events(&self, buf: &[u8]) -> Changes {
+        let header = mem::size_of::<libc::inotify_event>();
+        let mut watches = self.watches.lock().unwrap();
+        let mut changes = Changes::default();
+        let mut offset = 0;
+
+        while offset + header <= buf.len() {
+            let event: libc::inotify_event = unsafe { ptr::read_unaligned(buf[offset..].as_ptr() as *const _) };
+            let name = &buf[(offset + header).min(buf.len())..(offset + header + event.len as usize).min(buf.len())];
+            let name = &name[..name.iter().position(|x| *x == 0).unwrap_or(name.len())];
+            offset += header + event.len as usize;
+
+            if event.mask & libc::IN_Q_OVERFLOW != 0 {
+                changes.all = true;
+                continue;
+            }
+
+            match watches.get(&event.wd) {
+                Some(Watch::BaseDir) => match str::from_utf8(name) {
+                    Ok(name) if !name.is_empty() => { changes.themes.insert(name.to_string()); },
+                    // the base dir itself is gone
+                    _ => changes.all = true,
+                },
+                Some(Watch::Theme(names)) => changes.themes.extend(names.iter().cloned()),
+                None => {},
+            }
+
+            if event.mask & libc::IN_IGNORED != 0 {
+                watches.remove(&event.wd);
+            }
+        }
+
+        if changes.all {
+            for watch in watches.values() {
+                if let Watch::Theme(ref names) = *watch {
+                    changes.themes.extend(names.iter().cloned());
+                }
+            }
+        }
+
+        changes
+    }
+
+    /// Record changes for the lookup, then tell the callbacks.
+    fn notify(&self, changes: Changes) {
+        if !changes.all && changes.themes.is_empty() { return; }
+
+        let mut names: Vec<String> = changes.themes.iter().cloned().collect();
+        names.sort();
+
+        {
+            let mut pending = self.changes.lock().unwrap();
+            pending.all |= changes.all;
+            pending.themes.extend(changes.themes);
+        }
+
+        // callbacks may register other callbacks
+        let callbacks = self.callbacks.lock().unwrap().clone();
+        for name in names.iter() {
+            for callback in callbacks.iter() {
+                callback(name);
+            }
+        }
+    }
+}
+
+/// An inotify instance with a thread reading its events.
+struct Inotify {
+    fd: RawFd,
+    /// written to stop the thread
+    stop: [RawFd; 2],
+    state: Arc<WatchState>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Inotify {
+    fn new(callbacks: Arc<Mutex<Vec<Callback>>>) -> io::Result<Self> {
+        let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC | libc::IN_NONBLOCK) };
+        if fd < 0 { return Err(io::Error::last_os_error()); }
+
+        let mut stop = [-1; 2];
+        if unsafe { libc::pipe2(stop.as_mut_ptr(), libc::O_CLOEXEC) } < 0 {
+            let e = io::Error::last_os_error();
+            unsafe { libc::close(fd) };
+            return Err(e);
+        }
+
+        let mut r = Self {
+            fd,
+            stop,
+            state: Arc::new(WatchState { callbacks, ..Default::default() }),
+            thread: None,
+        };
+
+        let state = r.state.clone();
+        let thread = thread::Builder::new().name("icon-theme-watcher".to_string()).spawn(move || {
+            read_events(fd, stop[0], &state);
+
+            if state.detached.load(Ordering::SeqCst) {
+                close_fds(fd, stop);
+            }
+        })?;
+        r.thread = Some(thread);
+
+        Ok(r)
+    }
+
+    fn add_watch(&self, dir: &Path, theme: Option<&str>) -> io::Result<()> {
+        let path = CString::new(dir.as_os_str().as_bytes())?;
+
+        // hold the lock so events of a new watch are not dropped as unknown
+        let mut watches = self.state.watches.lock().unwrap();
+        let wd = unsafe { libc::inotify_add_watch(self.fd, path.as_ptr(), WATCH_MASK) };
+        if wd < 0 { return Err(io::Error::last_os_error()); }
+
+        match theme {
+            None => { watches.insert(wd, Watch::BaseDir); },
+            Some(name) => if let Watch::Theme(ref mut names) = *watches.entry(wd).or_insert_with(|| Watch::Theme(HashSet::new())) {
+                names.insert(name.to_string());
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Stop watching dirs of `theme`, or of all themes if `None`, unless other themes share them.
+    fn remove_theme_watches(&self, theme: Option<&str>) {
+        let mut watches = self.state.watches.lock().unwrap();
+
+        watches.retain(|wd, watch| {
+            let names = match *watch {
+                Watch::BaseDir => return true,
+                Watch::Theme(ref mut names) => names,
+            };
+
+            match theme {
+                Some(name) => { names.remove(name); },
+                None => names.clear(),
+            }
+            if !names.is_empty() { return true; }
+
+            unsafe { libc::inotify_rm_watch(self.fd, *wd) };
+            false
+        });
+    }
+}
+
+impl Drop for Inotify {
+    fn drop(&mut self) {
+        unsafe { libc::write(self.stop[1], b"q".as_ptr() as *const _, 1) };
+
+        match self.thread.take() {
+            // a callback disabled the watcher or dropped the lookup, the thread can't join itself
+            Some(ref thread) if thread.thread().id() == thread::current().id() => {
+                self.state.detached.store(true, Ordering::SeqCst);
+                return;
+            },
+            Some(thread) => { let _ = thread.join(); },
+            None => {},
+        }
+
+        close_fds(self.fd, self.stop);
+    }
+}
+
+fn close_fds(fd: RawFd, stop: [RawFd; 2]) {
+    unsafe {
+        libc::close(fd);
+        libc::close(stop[0]);
+        libc::close(stop[1]);
+    }
+}
+
+fn read_events(fd: RawFd, stop: RawFd, state: &WatchState) {
+    let mut buf = vec![0u8; 64 * 1024];
+
+    loop {
+        let mut fds = [
+            libc::pollfd { fd, events: libc::POLLIN, revents: 0 },
+            libc::pollfd { fd: stop, events: libc::POLLIN, revents: 0 },
+        ];
+
+        if unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, -1) } < 0 {
+            if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted { continue; }
+            break;
+        }
+
+        if fds[1].revents != 0 { break; }
+        // POLLERR etc. without data
+        if fds[0].revents & libc::POLLIN == 0 { break; }
+
+        let len = unsafe { libc::read(fd, buf.as_mut_ptr() as *mut _, buf.len()) };
+        if len <= 0 {
+            match io::Error::last_os_error().kind() {
+                io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock => continue,
+                _ => break,
+            }
+        }
+
+        state.notify(state.parse_events(&buf[..len as usize]));
+    }
+}
+
+///
+/// Watch base dirs and loaded themes of an `IconLookup` with inotify.
+///
+/// Changed themes are recorded for the lookup to drop on next use, and the
+/// callbacks are called with the internal name of each changed theme from the
+/// watcher thread. Events of one read are coalesced, so installing a package
+/// calls back once per theme rather than once per file.
+///
+/// Base dirs that don't exist when watching starts are not watched.
+///
+#[derive(Default)]
+pub(crate) struct ThemeWatcher {
+    callbacks: Arc<Mutex<Vec<Callback>>>,
+    inotify: RwLock<Option<Inotify>>,
+}
+
+impl fmt::Debug for ThemeWatcher {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ThemeWatcher")
+         .field("enabled", &self.is_enabled())
+         .field("callbacks", &self.callbacks.lock().unwrap().len())
+         .finish()
+    }
+}
+
+impl ThemeWatcher {
+    /// Start watching `base_dirs` and `themes`, or stop watching anything.
+    pub(crate) fn enable<'a, I>(&self, enable: bool, base_dirs: &[PathBuf], themes: I) -> io::Result<()>
+      where I: IntoIterator<Item = &'a IconTheme> {
+
+        let mut inotify = self.inotify.write().unwrap();
+
+        if !enable {
+            *inotify = None;
+            return Ok(());
+        }
+
+        if inotify.is_none() {
+            let new = Inotify::new(self.callbacks.clone())?;
+            for dir in base_dirs {
+                if let Err(e) = new.add_watch(dir, None) {
+                    debug!("failed to watch {}: {}", dir.display(), e);
+                }
+            }
+
+            *inotify = Some(new);
+        }
+
+        if let Some(ref inotify) = *inotify {
+            for theme in themes {
+                watch_theme(inotify, theme);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.inotify.read().unwrap().is_some()
+    }
+
+    /// Watch the dirs of a loaded theme, nothing if watching is disabled.
+    pub(crate) fn watch_theme(&self, theme: &IconTheme) {
+        if let Some(ref inotify) = *self.inotify.read().unwrap() {
+            watch_theme(inotify, theme);
+        }
+    }
+
+    /// Stop watching the dirs of a purged theme.
+    pub(crate) fn unwatch_theme(&self, name: &str) {
+        if let Some(ref inotify) = *self.inotify.read().unwrap() {
+            inotify.remove_theme_watches(Some(name));
+        }
+    }
+
+    /// Stop watching the dirs of all themes, base dirs are still watched.
+    pub(crate) fn unwatch_themes(&self) {
+        if let Some(ref inotify) = *self.inotify.read().unwrap() {
+            inotify.remove_theme_watches(None);
+        }
+    }
+
+    pub(crate) fn connect<F>(&self, callback: F)
+      where F: Fn(&str) + Send + Sync + 'static {
+        self.callbacks.lock().unwrap().push(Arc::new(callback));
+    }
+
+    /// Themes changed since the last call, `None` if nothing changed.
+    pub(crate) fn take_changes(&self) -> Option<Changes> {
+        let inotify = self.inotify.read().unwrap();
+        let mut changes = inotify.as_ref()?.state.changes.lock().unwrap();
+
+        if !changes.all && changes.themes.is_empty() {
+            return None;
+        }
+
+        Some(mem::take(&mut *changes))
+    }
+}
+
+/// Watch the theme dirs in every base dir, and the parents of nested sub dirs so
+/// sub dirs created later are noticed.
+fn watch_theme(inotify: &Inotify, theme: &IconTheme) {
+    let mut dirs: Vec<PathBuf> = vec![];
+
+    for base in theme.base_dirs() {
+        dirs.push(base.clone());
+
+        for sub in theme.directories() {
+            let mut dir = base.clone();
+            for component in Path::new(sub.name()).components() {
+                dir.push(component);
+                dirs.push(dir.clone());
+            }
+        }
+    }
+
+    dirs.sort();
+    dirs.dedup();
+
+    for dir in dirs.iter().filter(|x| x.is_dir()) {
+        if let Err(e) = inotify.add_watch(dir, Some(theme.internal_name())) {
+            debug!("failed to watch {}: {}", dir.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use icon_lookup::IconLookup;
+    use icon_theme::scratch_theme;
+
+    use std::fs;
+    use std::sync::{mpsc, Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    /// Wait for the watcher thread to report `theme`.
+    fn wait_for(changed: &Mutex<Vec<String>>, theme: &str, timeout: Duration) -> bool {
+        let start = Instant::now();
+        while start.elapsed() < timeout {
+            if changed.lock().unwrap().iter().any(|x| x == theme) { return true; }
+            ::std::thread::sleep(Duration::from_millis(10));
+        }
+
+        false
+    }
+
+    #[test]
+    fn test_watcher() {
+        let base = ::std::path::Path::new("target/test-watcher");
+        let theme_dir = base.join("watched");
+        let _ = fs::remove_dir_all(base);
+        scratch_theme::create(&theme_dir, &["first"]);
+
+        let lookup = IconLookup::new(vec![base]).with_fallback_theme("watched").with_watcher(true);
+        let changed = Arc::new(Mutex::new(vec![]));
+        let c = changed.clone();
+        lookup.on_theme_changed(move |name| c.lock().unwrap().push(name.to_string()));
+
+        assert!(lookup.find_icon_with_theme_name("watched", "first", 16, 1).is_some());
+        assert!(lookup.find_icon_with_theme_name("watched", "second", 16, 1).is_none());
+
+        // icons installed into a loaded theme
+        let second = scratch_theme::install(&theme_dir, "second");
+        assert!(wait_for(&changed, "watched", Duration::from_secs(5)));
+        assert_eq!(lookup.find_icon_with_theme_name("watched", "second", 16, 1), Some(second.clone()));
+
+        // a new theme
+        fs::create_dir_all(base.join("installed")).unwrap();
+        assert!(wait_for(&changed, "installed", Duration::from_secs(5)));
+
+        // purged themes are not watched anymore
+        assert!(lookup.purge_theme("watched"));
+        changed.lock().unwrap().clear();
+        scratch_theme::install(&theme_dir, "third");
+        assert!(!wait_for(&changed, "watched", Duration::from_millis(200)));
+
+        // disabled watchers don't call back
+        lookup.enable_watcher(false);
+        changed.lock().unwrap().clear();
+        fs::remove_file(second).unwrap();
+        assert!(!wait_for(&changed, "watched", Duration::from_millis(200)));
+    }
+
+    #[test]
+    fn test_stop_from_callback() {
+        let base = ::std::path::Path::new("target/test-watcher-callback");
+        let _ = fs::remove_dir_all(base);
+        fs::create_dir_all(base).unwrap();
+
+        // disable the watcher from its own thread
+        let lookup = Arc::new(IconLookup::new(vec![base]).with_watcher(true));
+        let weak = Arc::downgrade(&lookup);
+        let (tx, rx) = mpsc::channel();
+        lookup.on_theme_changed(move |_| if let Some(lookup) = weak.upgrade() {
+            let _ = tx.send(lookup.enable_watcher(false));
+        });
+
+        fs::create_dir(base.join("disabled")).unwrap();
+        assert_eq!(rx.recv_timeout(Duration::from_secs(5)), Ok(false));
+
+        // drop the last reference of the lookup from its watcher thread
+        let lookup = Arc::new(IconLookup::new(vec![base]).with_watcher(true));
+        let slot = Arc::new(Mutex::new(Some(lookup.clone())));
+        let (tx, rx) = mpsc::channel();
+        {
+            let slot = slot.clone();
+            lookup.on_theme_changed(move |_| {
+                drop(slot.lock().unwrap().take());
+                let _ = tx.send(());
+            });
+        }
+        drop(lookup);
+
+        fs::create_dir(base.join("dropped")).unwrap();
+        assert_eq!(rx.recv_timeout(Duration::from_secs(5)), Ok(()));
+        assert!(slot.lock().unwrap().is_none());
+    }
+}